@@ -1,293 +1,612 @@
-use crate::state::{ACTIVE_RESET_SESSION, ActiveResetSession};
+use crate::config::{SessionPolicy, TargetProcess};
+use crate::hooks::BuildHookReport;
+use crate::journal::{self, JOURNAL_LOCK, Journal};
+use crate::matcher::PatternSet;
+use crate::ownership::{PropertyOwners, PropertyValue};
+use crate::supervisor::{SessionKey, SpoofedProperties, Supervisor};
 use log::{error, warn};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::cell::OnceCell;
 use std::collections::HashMap;
+use std::fmt;
 use std::fs;
 use std::io::{Read, Write};
 use std::os::unix::net::UnixStream;
-use std::thread;
-use std::time::Duration;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
 use zygisk_api::api::{V4, ZygiskApi};
 
-pub fn spoof_system_props_via_companion(
-    api: &mut ZygiskApi<V4>,
-    prop_map: &HashMap<String, String>,
-    package_name: &str,
-) -> anyhow::Result<()> {
-    if prop_map.is_empty() {
-        return Ok(());
-    }
-
-    let request = CompanionRequest::Apply(ResetpropSessionRequest {
-        pid: std::process::id(),
-        props: prop_map.clone(),
-    });
-
-    let response = send_companion_command(api, &request)?;
-    if response.status != 0 {
-        anyhow::bail!(
-            response
-                .message
-                .unwrap_or_else(|| "companion resetprop failed".to_string())
-        );
-    }
-
-    if let Some(backups) = response.backups {
-        *ACTIVE_RESET_SESSION.lock().unwrap() = Some(ActiveResetSession {
-            package: package_name.to_string(),
-            backups,
-        });
-    } else {
-        warn!("Companion did not return property backups; automatic restore may be skipped");
-    }
+/// 注入状态文件所在目录
+const STATUS_DIR: &str = "/data/adb/device_faker/status";
+
+/// companion 协议版本，协议结构发生不兼容变化时递增
+const PROTOCOL_VERSION: u32 = 2;
+/// 能够互通的最低协议版本，新增命令通过 Capability 协商而不是提升此版本
+const MIN_PROTOCOL_VERSION: u32 = 2;
+/// 没有握手的旧版协议
+const LEGACY_PROTOCOL_VERSION: u32 = 1;
+
+/// 当前版本支持的命令
+const CAPABILITIES: [Capability; 4] = [
+    Capability::Resetprop,
+    Capability::HideProps,
+    Capability::Status,
+    Capability::SessionPolicy,
+];
+
+/// companion 在握手时声明的能力，模块据此判断能否发送某条命令
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    Resetprop,
+    HideProps,
+    Status,
+    SessionPolicy,
+    /// 更新版本 companion 声明的、当前模块不认识的能力
+    #[serde(other)]
+    Unknown,
+}
 
-    Ok(())
+/// companion 返回给模块的错误，模块按类型输出日志并选择回退方式
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CompanionError {
+    /// 双方协议版本不兼容，通常是更新模块后尚未重启
+    UnsupportedVersion {
+        module: u32,
+        companion: u32,
+    },
+    /// companion 版本较旧，不支持该命令
+    MissingCapability {
+        capability: Capability,
+    },
+    ResetpropNotFound,
+    /// 读取原始属性值失败，此时尚未修改任何属性
+    BackupFailed {
+        key: String,
+        message: String,
+    },
+    /// 修改属性失败，已修改的属性会被立即还原
+    ApplyFailed {
+        key: String,
+        message: String,
+    },
+    /// 无法写入还原日志，此时尚未修改任何属性
+    JournalFailed {
+        message: String,
+    },
+    /// 无法启动会话管理线程，此时尚未修改任何属性
+    SupervisorFailed {
+        message: String,
+    },
+    StatusFailed {
+        message: String,
+    },
+    InvalidRequest {
+        message: String,
+    },
+    /// 与 companion 通信失败
+    Transport {
+        message: String,
+    },
 }
 
-pub fn restore_previous_resetprop_if_needed(
-    api: &mut ZygiskApi<V4>,
-    current_package: &str,
-) -> anyhow::Result<()> {
-    let mut guard = ACTIVE_RESET_SESSION.lock().unwrap();
-    let pending = guard.take();
-
-    match pending {
-        Some(session) if session.package != current_package => {
-            if let Err(e) = restore_props_via_companion(api, &session.backups) {
-                error!("Failed to restore previous resetprop session: {e}");
+impl fmt::Display for CompanionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnsupportedVersion { module, companion } => write!(
+                f,
+                "companion protocol v{companion} is incompatible with module protocol v{module}, reboot to restart the companion"
+            ),
+            Self::MissingCapability { capability } => write!(
+                f,
+                "companion does not support {capability:?}, reboot to restart the companion"
+            ),
+            Self::ResetpropNotFound => write!(f, "resetprop binary not found in known locations"),
+            Self::BackupFailed { key, message } => {
+                write!(f, "failed to back up property {key}: {message}")
             }
-        }
-        other => {
-            *guard = other;
+            Self::ApplyFailed { key, message } => write!(
+                f,
+                "failed to apply property {key}: {message} (changes rolled back)"
+            ),
+            Self::JournalFailed { message } => {
+                write!(f, "failed to write restore journal: {message}")
+            }
+            Self::SupervisorFailed { message } => {
+                write!(f, "failed to start session supervisor: {message}")
+            }
+            Self::StatusFailed { message } => write!(f, "failed to write hook status: {message}"),
+            Self::InvalidRequest { message } => write!(f, "invalid companion request: {message}"),
+            Self::Transport { message } => write!(f, "failed to talk to companion: {message}"),
         }
     }
+}
 
-    Ok(())
+impl std::error::Error for CompanionError {}
+
+impl CompanionError {
+    fn transport(err: impl fmt::Display) -> Self {
+        Self::Transport {
+            message: err.to_string(),
+        }
+    }
 }
 
-fn restore_props_via_companion(
+/// 注入状态随请求一起发送，不再单独往返一次
+pub fn spoof_system_props_via_companion(
     api: &mut ZygiskApi<V4>,
-    backups: &HashMap<String, String>,
-) -> anyhow::Result<()> {
-    if backups.is_empty() {
-        return Ok(());
+    target: &TargetProcess,
+    prop_map: &HashMap<String, String>,
+    hide_props: &[String],
+    session_policy: SessionPolicy,
+    status: HookStatus,
+) -> Result<(), CompanionError> {
+    if prop_map.is_empty() && hide_props.is_empty() {
+        return report_hook_status(api, status);
     }
 
-    let request = CompanionRequest::Restore(RestoreRequest {
-        props: backups.clone(),
+    let request = CompanionRequest::Apply(ResetpropSessionRequest {
+        pid: std::process::id(),
+        package: target.package.clone(),
+        uid: target.uid,
+        props: prop_map.clone(),
+        hide_props: hide_props.to_vec(),
+        session_policy,
+        status: Some(status),
     });
 
-    let response = send_companion_command(api, &request)?;
-    if response.status != 0 {
-        anyhow::bail!(
-            response
-                .message
-                .unwrap_or_else(|| "companion restore failed".to_string())
-        );
-    }
+    // 还原由 companion 的会话管理线程负责，模块在注入完成后即可卸载
+    send_companion_command(api, &request)?;
+    Ok(())
+}
 
+/// 将本次注入结果交给 companion 写入状态文件，供 WebUI 等工具查看
+pub fn report_hook_status(
+    api: &mut ZygiskApi<V4>,
+    status: HookStatus,
+) -> Result<(), CompanionError> {
+    send_companion_command(api, &CompanionRequest::Status(status))?;
     Ok(())
 }
 
+/// 每个连接先交换协议版本与能力，确认 companion 支持该命令后再发送请求
 fn send_companion_command(
     api: &mut ZygiskApi<V4>,
     request: &CompanionRequest,
-) -> anyhow::Result<CompanionResponse> {
-    let payload = serde_json::to_vec(request)?;
+) -> Result<CompanionResponse, CompanionError> {
+    let required = request.required_capabilities();
     let response = api
-        .with_companion(|stream| -> anyhow::Result<CompanionResponse> {
-            stream.write_all(&(payload.len() as u32).to_le_bytes())?;
-            stream.write_all(&payload)?;
-            stream.flush()?;
-
-            let mut len_buf = [0u8; 4];
-            stream.read_exact(&mut len_buf)?;
-            let resp_len = u32::from_le_bytes(len_buf) as usize;
-            let mut resp_buf = vec![0u8; resp_len];
-            stream.read_exact(&mut resp_buf)?;
-
-            let resp = serde_json::from_slice::<CompanionResponse>(&resp_buf)?;
-            Ok(resp)
+        .with_companion(|stream| -> Result<CompanionResponse, CompanionError> {
+            write_frame(
+                stream,
+                &Handshake {
+                    version: PROTOCOL_VERSION,
+                    capabilities: CAPABILITIES.to_vec(),
+                },
+            )?;
+
+            // 旧版 companion 会把握手当作无效请求，回复的内容无法解析为 Handshake
+            let handshake: Handshake = read_frame(stream).map_err(|err| match err {
+                CompanionError::InvalidRequest { .. } => CompanionError::UnsupportedVersion {
+                    module: PROTOCOL_VERSION,
+                    companion: LEGACY_PROTOCOL_VERSION,
+                },
+                other => other,
+            })?;
+            if handshake.version < MIN_PROTOCOL_VERSION {
+                return Err(CompanionError::UnsupportedVersion {
+                    module: PROTOCOL_VERSION,
+                    companion: handshake.version,
+                });
+            }
+            if let Some(&capability) = required
+                .iter()
+                .find(|capability| !handshake.capabilities.contains(capability))
+            {
+                return Err(CompanionError::MissingCapability { capability });
+            }
+
+            write_frame(stream, request)?;
+            read_frame(stream)
         })
-        .map_err(|e| anyhow::anyhow!("Failed to talk to companion: {e}"))??;
+        .map_err(CompanionError::transport)??;
 
-    Ok(response)
+    match response.error {
+        Some(err) => Err(err),
+        None => Ok(response),
+    }
 }
 
 pub fn handle_companion_request(stream: &mut UnixStream) {
-    let response = match read_companion_request(stream) {
-        Ok(CompanionRequest::Apply(request)) => match apply_resetprop_session(request) {
-            Ok(backups) => CompanionResponse::ok_with_backups(backups),
-            Err(err) => {
-                error!("Companion failed to apply resetprop session: {err}");
-                CompanionResponse::err(err.to_string())
-            }
-        },
-        Ok(CompanionRequest::Restore(request)) => match restore_properties(request) {
-            Ok(_) => CompanionResponse::ok(),
+    let response = match accept_handshake(stream) {
+        Ok(()) => match read_frame::<CompanionRequest>(stream) {
+            Ok(request) => execute_request(request),
+            // 模块在握手后发现版本或能力不满足时会直接断开
+            Err(CompanionError::Transport { .. }) => return,
             Err(err) => {
-                error!("Companion failed to restore properties: {err}");
-                CompanionResponse::err(err.to_string())
+                error!("Companion failed to parse request: {err}");
+                CompanionResponse::err(err)
             }
         },
         Err(err) => {
-            error!("Companion failed to parse request: {err}");
-            CompanionResponse::err("invalid request")
+            error!("Companion handshake failed: {err}");
+            CompanionResponse::err(err)
         }
     };
 
-    if let Err(e) = write_companion_response(stream, &response) {
+    if let Err(e) = write_frame(stream, &response) {
         warn!("Failed to write companion response: {e}");
     }
 }
 
-fn read_companion_request(stream: &mut UnixStream) -> anyhow::Result<CompanionRequest> {
+/// 读取模块的握手并回复本端的版本与能力，版本过旧的模块返回错误
+fn accept_handshake(stream: &mut UnixStream) -> Result<(), CompanionError> {
+    let handshake: Handshake = read_frame(stream).map_err(|err| match err {
+        CompanionError::InvalidRequest { .. } => CompanionError::UnsupportedVersion {
+            module: LEGACY_PROTOCOL_VERSION,
+            companion: PROTOCOL_VERSION,
+        },
+        other => other,
+    })?;
+
+    write_frame(
+        stream,
+        &Handshake {
+            version: PROTOCOL_VERSION,
+            capabilities: CAPABILITIES.to_vec(),
+        },
+    )?;
+
+    if handshake.version < MIN_PROTOCOL_VERSION {
+        return Err(CompanionError::UnsupportedVersion {
+            module: handshake.version,
+            companion: PROTOCOL_VERSION,
+        });
+    }
+    Ok(())
+}
+
+fn execute_request(request: CompanionRequest) -> CompanionResponse {
+    let result = match request {
+        CompanionRequest::Apply(mut request) => {
+            // 状态文件与伪装结果无关，写入失败只记录日志
+            if let Some(status) = request.status.take()
+                && let Err(err) = write_hook_status(status)
+            {
+                error!("Companion {err}");
+            }
+            // 每次 Apply 先回放上次遗留的日志，保证新会话备份到的是真实原始值
+            let _journal_guard = JOURNAL_LOCK
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            replay_journals();
+            apply_resetprop_session(request).map(|_| CompanionResponse::ok())
+        }
+        CompanionRequest::Status(status) => {
+            write_hook_status(status).map(|_| CompanionResponse::ok())
+        }
+    };
+
+    result.unwrap_or_else(|err| {
+        error!("Companion request failed: {err}");
+        CompanionResponse::err(err)
+    })
+}
+
+/// 还原无人负责的日志，调用方需持有 [`JOURNAL_LOCK`]
+fn replay_journals() {
+    let Some(resetprop_path) = find_resetprop_path() else {
+        return;
+    };
+
+    // 只有存在需要回放的日志时才读取当前属性
+    let current = OnceCell::new();
+    journal::replay_unfinished(|key, expected, original| match expected {
+        Some(expected) => {
+            let current = current
+                .get_or_init(|| list_properties().map_err(|err| format!("{err:#}")))
+                .as_ref()
+                .map_err(|err| anyhow::anyhow!("{err}"))?;
+            compare_and_write(
+                &resetprop_path,
+                current,
+                key,
+                expected.as_deref(),
+                original.as_deref(),
+            )
+            .map(|_| ())
+        }
+        // 旧版本日志没有记录写入的值
+        None => write_property(&resetprop_path, key, original.as_deref()),
+    });
+}
+
+/// 帧格式：4 字节小端长度 + JSON 内容
+fn read_frame<T: DeserializeOwned>(stream: &mut UnixStream) -> Result<T, CompanionError> {
     let mut len_buf = [0u8; 4];
-    stream.read_exact(&mut len_buf)?;
+    stream
+        .read_exact(&mut len_buf)
+        .map_err(CompanionError::transport)?;
     let payload_len = u32::from_le_bytes(len_buf) as usize;
     if payload_len == 0 {
-        anyhow::bail!("empty request payload");
+        return Err(CompanionError::InvalidRequest {
+            message: "empty payload".to_string(),
+        });
     }
 
     let mut payload = vec![0u8; payload_len];
-    stream.read_exact(&mut payload)?;
-    let request = serde_json::from_slice::<CompanionRequest>(&payload)?;
-    Ok(request)
+    stream
+        .read_exact(&mut payload)
+        .map_err(CompanionError::transport)?;
+    serde_json::from_slice(&payload).map_err(|err| CompanionError::InvalidRequest {
+        message: err.to_string(),
+    })
 }
 
-fn write_companion_response(
-    stream: &mut UnixStream,
-    response: &CompanionResponse,
-) -> anyhow::Result<()> {
-    let bytes = serde_json::to_vec(response)?;
-    stream.write_all(&(bytes.len() as u32).to_le_bytes())?;
-    stream.write_all(&bytes)?;
-    stream.flush()?;
-    Ok(())
+fn write_frame(stream: &mut UnixStream, value: &impl Serialize) -> Result<(), CompanionError> {
+    let bytes = serde_json::to_vec(value).map_err(CompanionError::transport)?;
+    stream
+        .write_all(&(bytes.len() as u32).to_le_bytes())
+        .and_then(|_| stream.write_all(&bytes))
+        .and_then(|_| stream.flush())
+        .map_err(CompanionError::transport)
 }
 
-fn apply_resetprop_session(
-    request: ResetpropSessionRequest,
-) -> anyhow::Result<HashMap<String, String>> {
-    if request.props.is_empty() {
-        return Ok(HashMap::new());
+/// 先备份全部属性再修改；修改中途失败时立即还原，不会留下半伪装的状态
+fn apply_resetprop_session(request: ResetpropSessionRequest) -> Result<(), CompanionError> {
+    if request.props.is_empty() && request.hide_props.is_empty() {
+        return Ok(());
     }
 
-    let resetprop_path = find_resetprop_path()
-        .ok_or_else(|| anyhow::anyhow!("resetprop binary not found in known locations"))?;
+    // 请求发自 specialize 之前，此时进程仍是 uid 0 的 zygote 子进程，无法由 pid 推断；
+    // uid 0 的会话会把所有 root 进程当作自己的进程，永远不会结束
+    if request.uid == 0 {
+        return Err(CompanionError::InvalidRequest {
+            message: format!("missing uid for {}", request.package),
+        });
+    }
+
+    let resetprop_path = find_resetprop_path().ok_or(CompanionError::ResetpropNotFound)?;
+    let supervisor = Supervisor::get().map_err(|err| CompanionError::SupervisorFailed {
+        message: format!("{err:#}"),
+    })?;
+
+    // 备份到登记完成之间持有会话表，避免管理线程同时还原同一批属性
+    let mut table = supervisor.lock();
+    let key = SessionKey {
+        package: request.package,
+        uid: request.uid,
+    };
+    if let Some(session) = table.find(&key) {
+        // 同一应用的其他进程：属性已经处于伪装状态，再次备份会把伪装值当作原始值
+        session.add_process(request.pid);
+        drop(table);
+        supervisor.wake();
+        return Ok(());
+    }
 
-    let mut backups = Vec::with_capacity(request.props.len());
-    for key in request.props.keys() {
-        let original = backup_property(key)?;
+    // 一次列出所有属性作为备份，避免逐个属性 fork getprop
+    let current = list_properties().map_err(|err| CompanionError::BackupFailed {
+        key: "getprop".to_string(),
+        message: format!("{err:#}"),
+    })?;
+    // 其他会话已接管的属性读到的是伪装值，改用仲裁表记录的真实原始值
+    let owners = table.owners();
+    let hidden = find_hidden_properties(&request.hide_props, &request.props, &current, owners)
+        .map_err(|err| CompanionError::BackupFailed {
+            key: "hide_props".to_string(),
+            message: format!("{err:#}"),
+        })?;
+
+    let mut props = request.props;
+    let mut backups = Vec::with_capacity(props.len() + hidden.len());
+    let mut unchanged = Vec::new();
+    for (key, value) in &props {
+        let original = match owners.original(key) {
+            Some(original) => original.clone(),
+            // 已经是目标值且无人接管的属性无需写入，也不需要还原
+            None if current.get(key) == Some(value) => {
+                unchanged.push(key.clone());
+                continue;
+            }
+            // 原本不存在的属性记录为 None，还原时删除
+            None => current.get(key).cloned(),
+        };
         backups.push(PropBackup {
             key: key.clone(),
             original_value: original,
         });
     }
+    for (key, value) in &hidden {
+        backups.push(PropBackup {
+            key: key.clone(),
+            original_value: Some(value.clone()),
+        });
+    }
+
+    props.retain(|key, _| !unchanged.contains(key));
+    if backups.is_empty() {
+        return Ok(());
+    }
 
-    let backups_for_response: HashMap<String, String> = backups
+    let originals: HashMap<String, PropertyValue> = backups
         .iter()
         .map(|entry| (entry.key.clone(), entry.original_value.clone()))
         .collect();
 
-    for (key, value) in &request.props {
-        apply_resetprop(&resetprop_path, key, value)?;
+    let hidden: Vec<String> = hidden.into_keys().collect();
+    let spoofed = props
+        .iter()
+        .map(|(key, value)| (key.clone(), Some(value.clone())))
+        .chain(hidden.iter().map(|key| (key.clone(), None)))
+        .collect();
+    let mut journal = Journal::create(request.pid, originals, spoofed).map_err(|err| {
+        CompanionError::JournalFailed {
+            message: format!("{err:#}"),
+        }
+    })?;
+
+    // 记录失败时下次调用会提前回放该日志，只会缩短伪装时长
+    if request.session_policy != SessionPolicy::Reboot
+        && let Err(e) = journal.set_watcher(std::process::id())
+    {
+        error!("Failed to record session owner in journal: {e:#}");
     }
 
-    spawn_restore_watcher(request.pid, backups, resetprop_path)?;
+    let spoof = SpoofedProperties {
+        props,
+        hidden,
+        backups,
+    };
+    table
+        .start(
+            key,
+            request.pid,
+            request.session_policy,
+            spoof,
+            resetprop_path,
+            journal,
+        )
+        .map_err(|(key, err)| CompanionError::ApplyFailed {
+            key,
+            message: format!("{err:#}"),
+        })?;
+    drop(table);
+    supervisor.wake();
 
-    Ok(backups_for_response)
+    Ok(())
 }
 
-fn restore_properties(request: RestoreRequest) -> anyhow::Result<()> {
-    if request.props.is_empty() {
-        return Ok(());
-    }
-
-    let resetprop_path = find_resetprop_path()
-        .ok_or_else(|| anyhow::anyhow!("resetprop binary not found in known locations"))?;
-
-    for (key, value) in request.props {
-        apply_resetprop(&resetprop_path, &key, &value)?;
+/// 状态文件按进程名保存为 `<STATUS_DIR>/<进程名>.json`，同一进程重启后覆盖旧记录
+fn write_hook_status(mut status: HookStatus) -> Result<(), CompanionError> {
+    if status.process.is_empty() || status.process.contains('/') || status.process.starts_with('.')
+    {
+        return Err(CompanionError::InvalidRequest {
+            message: format!("invalid process name '{}'", status.process),
+        });
     }
 
-    Ok(())
+    status.timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+
+    let path = Path::new(STATUS_DIR).join(format!("{}.json", status.process));
+    let tmp_path = path.with_extension("json.tmp");
+    let written = serde_json::to_vec_pretty(&status)
+        .map_err(std::io::Error::other)
+        .and_then(|bytes| {
+            fs::create_dir_all(STATUS_DIR)?;
+            fs::write(&tmp_path, bytes)?;
+            fs::rename(&tmp_path, &path)
+        });
+    written.map_err(|err| CompanionError::StatusFailed {
+        message: err.to_string(),
+    })
 }
 
-fn backup_property(key: &str) -> anyhow::Result<String> {
-    let output = std::process::Command::new("getprop").arg(key).output()?;
-    if !output.status.success() {
-        anyhow::bail!("getprop failed for {key}");
+/// 列出当前系统中命中 hide_props 的属性及其原始值，props 中显式伪装的属性不会被隐藏
+fn find_hidden_properties(
+    hide_props: &[String],
+    spoofed: &HashMap<String, String>,
+    current: &HashMap<String, String>,
+    owners: &PropertyOwners,
+) -> anyhow::Result<HashMap<String, String>> {
+    if hide_props.is_empty() {
+        return Ok(HashMap::new());
     }
 
-    let value = String::from_utf8_lossy(&output.stdout)
-        .trim_end_matches(['\n', '\r'])
-        .to_string();
-    Ok(value)
+    let patterns = PatternSet::new(hide_props)?;
+    // 被接管的属性可能已被隐藏或伪装，以真实原始值为准
+    let mut candidates: HashMap<&str, &str> = current
+        .iter()
+        .map(|(key, value)| (key.as_str(), value.as_str()))
+        .collect();
+    for (key, original) in owners.originals() {
+        match original {
+            Some(original) => candidates.insert(key, original),
+            None => candidates.remove(key),
+        };
+    }
+    let hidden = candidates
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .filter(|(key, _)| !spoofed.contains_key(key) && patterns.best_match(key).is_some())
+        .collect();
+    Ok(hidden)
 }
 
-fn apply_resetprop(path: &str, key: &str, value: &str) -> anyhow::Result<()> {
+fn delete_resetprop(path: &str, key: &str) -> anyhow::Result<()> {
     let status = std::process::Command::new(path)
+        .arg("--delete")
         .arg(key)
-        .arg(value)
         .status()?;
     if !status.success() {
-        anyhow::bail!("resetprop failed for {key}");
+        anyhow::bail!("resetprop --delete failed for {key}");
     }
     Ok(())
 }
 
-fn spawn_restore_watcher(
-    pid: u32,
-    backups: Vec<PropBackup>,
-    resetprop_path: String,
-) -> anyhow::Result<()> {
-    unsafe {
-        match libc::fork() {
-            -1 => anyhow::bail!("fork failed: {}", std::io::Error::last_os_error()),
-            0 => {
-                if libc::setsid() == -1 {
-                    libc::_exit(1);
-                }
-                wait_for_process_inactive(pid);
-                for entry in backups {
-                    if let Err(e) =
-                        apply_resetprop(&resetprop_path, &entry.key, &entry.original_value)
-                    {
-                        error!(
-                            "Failed to restore property {} for pid {}: {}",
-                            entry.key, pid, e
-                        );
-                    }
-                }
-                libc::_exit(0);
-            }
-            _ => Ok(()),
-        }
+/// 属性仍是我们写入的 expected（None 表示已被删除）时才写入 value，返回是否写入
+///
+/// 期间被用户或其他模块修改过的属性保持不变，只记录冲突。
+///
+/// current 为写入前通过 [`list_properties`] 读取的全部属性。
+pub fn compare_and_write(
+    path: &str,
+    current: &HashMap<String, String>,
+    key: &str,
+    expected: Option<&str>,
+    value: Option<&str>,
+) -> anyhow::Result<bool> {
+    let current = current.get(key).map(String::as_str);
+    if current != expected {
+        warn!("Property {key} was changed to {current:?} by someone else, not restoring it");
+        return Ok(false);
     }
+    write_property(path, key, value)?;
+    Ok(true)
 }
 
-fn wait_for_process_inactive(pid: u32) {
-    let proc_path = format!("/proc/{pid}");
-    loop {
-        if !std::path::Path::new(&proc_path).exists() {
-            break;
-        }
+/// 一次 getprop 列出当前所有属性
+pub fn list_properties() -> anyhow::Result<HashMap<String, String>> {
+    let output = std::process::Command::new("getprop").output()?;
+    if !output.status.success() {
+        anyhow::bail!("getprop failed to list properties");
+    }
 
-        if !is_process_in_top_app(pid) {
-            break;
-        }
+    // getprop 的输出格式为 `[key]: [value]`
+    let properties = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.split_once("]: [")?;
+            let key = key.strip_prefix('[')?;
+            let value = value.strip_suffix(']')?;
+            Some((key.to_string(), value.to_string()))
+        })
+        .collect();
+    Ok(properties)
+}
 
-        thread::sleep(Duration::from_millis(500));
+/// 写入仲裁后的属性值：Some 为设置，None 为删除
+pub fn write_property(path: &str, key: &str, value: Option<&str>) -> anyhow::Result<()> {
+    match value {
+        Some(value) => apply_resetprop(path, key, value),
+        None => delete_resetprop(path, key),
     }
 }
 
-fn is_process_in_top_app(pid: u32) -> bool {
-    let cgroup_path = format!("/proc/{pid}/cgroup");
-    match fs::read_to_string(&cgroup_path) {
-        Ok(content) => content.lines().any(|line| line.contains("top-app")),
-        Err(_) => true,
+pub fn apply_resetprop(path: &str, key: &str, value: &str) -> anyhow::Result<()> {
+    let status = std::process::Command::new(path)
+        .arg(key)
+        .arg(value)
+        .status()?;
+    if !status.success() {
+        anyhow::bail!("resetprop failed for {key}");
     }
+    Ok(())
 }
 
 fn find_resetprop_path() -> Option<String> {
@@ -318,56 +637,97 @@ fn find_resetprop_path() -> Option<String> {
 #[derive(Serialize, Deserialize, Debug)]
 struct ResetpropSessionRequest {
     pid: u32,
+    /// 会话按包名与 uid 区分，同一应用的所有进程共用一个会话
+    #[serde(default)]
+    package: String,
+    #[serde(default)]
+    uid: u32,
     props: HashMap<String, String>,
+    #[serde(default)]
+    hide_props: Vec<String>,
+    #[serde(default)]
+    session_policy: SessionPolicy,
+    /// 随 Apply 一起写入的注入状态，旧版本 companion 会忽略该字段
+    #[serde(default)]
+    status: Option<HookStatus>,
 }
 
+/// 单个进程的注入结果，timestamp 由 companion 写入时填充
 #[derive(Serialize, Deserialize, Debug)]
-struct RestoreRequest {
-    props: HashMap<String, String>,
+pub struct HookStatus {
+    package: String,
+    process: String,
+    pid: u32,
+    mode: String,
+    timestamp: u64,
+    build: BuildHookReport,
+}
+
+impl HookStatus {
+    pub fn new(package: &str, process: &str, mode: &str, build: BuildHookReport) -> Self {
+        Self {
+            package: package.to_string(),
+            process: process.to_string(),
+            pid: std::process::id(),
+            mode: mode.to_string(),
+            timestamp: 0,
+            build,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "cmd", content = "payload")]
 enum CompanionRequest {
     Apply(ResetpropSessionRequest),
-    Restore(RestoreRequest),
+    Status(HookStatus),
+}
+
+impl CompanionRequest {
+    /// 执行该命令所需的 companion 能力
+    fn required_capabilities(&self) -> Vec<Capability> {
+        match self {
+            Self::Apply(request) => {
+                let mut required = vec![Capability::Resetprop];
+                if !request.hide_props.is_empty() {
+                    required.push(Capability::HideProps);
+                }
+                // 不支持该能力的 companion 会按默认策略处理
+                if request.session_policy != SessionPolicy::default() {
+                    required.push(Capability::SessionPolicy);
+                }
+                required
+            }
+            Self::Status(_) => vec![Capability::Status],
+        }
+    }
+}
+
+/// 每个连接的第一帧，模块与 companion 各发送一次
+#[derive(Serialize, Deserialize, Debug)]
+struct Handshake {
+    version: u32,
+    capabilities: Vec<Capability>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 struct CompanionResponse {
-    status: i32,
-    message: Option<String>,
-    backups: Option<HashMap<String, String>>,
+    error: Option<CompanionError>,
 }
 
 impl CompanionResponse {
     fn ok() -> Self {
-        Self {
-            status: 0,
-            message: None,
-            backups: None,
-        }
+        Self { error: None }
     }
 
-    fn err(msg: impl Into<String>) -> Self {
-        Self {
-            status: -1,
-            message: Some(msg.into()),
-            backups: None,
-        }
-    }
-
-    fn ok_with_backups(backups: HashMap<String, String>) -> Self {
-        Self {
-            status: 0,
-            message: None,
-            backups: Some(backups),
-        }
+    fn err(error: CompanionError) -> Self {
+        Self { error: Some(error) }
     }
 }
 
 #[derive(Clone)]
-struct PropBackup {
-    key: String,
-    original_value: String,
+pub struct PropBackup {
+    pub key: String,
+    /// None 表示属性原本不存在
+    pub original_value: PropertyValue,
 }
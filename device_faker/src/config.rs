@@ -1,14 +1,11 @@
-use anyhow::Result;
-use serde::Deserialize;
+use crate::matcher::{PatternSet, Specificity, glob_match};
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Deserializer, Serialize};
 use std::collections::HashMap;
 
-/// 机型设备信息模板
-#[derive(Debug, Clone, Deserialize)]
-pub struct DeviceTemplate {
-    /// 包名列表
-    #[serde(default)]
-    pub packages: Vec<String>,
-    /// 设备信息
+/// 设备信息与工作模式字段，模板与 [[apps]] 共用，支持逐字段合并
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct DeviceProfile {
     #[serde(default)]
     pub manufacturer: Option<String>,
     #[serde(default)]
@@ -27,37 +24,46 @@ pub struct DeviceTemplate {
     pub fingerprint: Option<String>,
     #[serde(default)]
     pub characteristics: Option<String>,
-    /// 是否为匹配的应用强制执行 FORCE_DENYLIST_UNMOUNT（默认继承全局设置）
+    /// 以下字段对应 android.os.Build 中的同名静态字段
     #[serde(default)]
-    pub force_denylist_unmount: Option<bool>,
-    /// 模板的工作模式（可选）
+    pub hardware: Option<String>,
     #[serde(default)]
-    pub mode: Option<String>,
-}
-
-#[derive(Debug, Clone, Deserialize)]
-pub struct AppConfig {
-    pub package: String,
-    /// 直接指定设备信息
+    pub board: Option<String>,
     #[serde(default)]
-    pub manufacturer: Option<String>,
+    pub bootloader: Option<String>,
+    #[serde(default, rename = "id")]
+    pub build_id: Option<String>,
     #[serde(default)]
-    pub brand: Option<String>,
+    pub display: Option<String>,
     #[serde(default)]
-    pub marketname: Option<String>,
+    pub host: Option<String>,
     #[serde(default)]
-    pub model: Option<String>,
+    pub user: Option<String>,
     #[serde(default)]
-    pub name: Option<String>,
+    pub tags: Option<String>,
+    #[serde(default, rename = "type")]
+    pub build_type: Option<String>,
+    /// 构建时间（毫秒时间戳，对应 Build.TIME）
     #[serde(default)]
-    pub device: Option<String>,
+    pub time: Option<i64>,
     #[serde(default)]
-    pub product: Option<String>,
+    pub soc_manufacturer: Option<String>,
     #[serde(default)]
-    pub fingerprint: Option<String>,
+    pub soc_model: Option<String>,
     #[serde(default)]
-    pub characteristics: Option<String>,
-    /// 是否为该应用强制执行 FORCE_DENYLIST_UNMOUNT（默认继承全局设置）
+    pub sku: Option<String>,
+    #[serde(default)]
+    pub odm_sku: Option<String>,
+    #[serde(default)]
+    pub supported_abis: Option<Vec<String>>,
+    #[serde(default)]
+    pub supported_32_bit_abis: Option<Vec<String>>,
+    #[serde(default)]
+    pub supported_64_bit_abis: Option<Vec<String>>,
+    /// android.os.Build.VERSION 字段（`[templates.xxx.version]` 子表）
+    #[serde(default)]
+    pub version: VersionProfile,
+    /// 是否强制执行 FORCE_DENYLIST_UNMOUNT（默认继承全局设置）
     #[serde(default)]
     pub force_denylist_unmount: Option<bool>,
     /// 工作模式：
@@ -66,6 +72,298 @@ pub struct AppConfig {
     /// - "resetprop": 使用 resetprop 工具修改属性（需要 Root，不可卸载）
     #[serde(default)]
     pub mode: Option<String>,
+    /// resetprop 模式下伪装属性的保留时长，默认 "foreground"
+    #[serde(default)]
+    pub session_policy: Option<SessionPolicy>,
+    /// 是否同时 Hook native 层的 `__system_property_get` 等函数（仅 full 模式生效，默认关闭）
+    /// 用于 Unity / Unreal 等直接在 native 代码中读取属性的应用
+    #[serde(default)]
+    pub native_props: Option<bool>,
+    /// 需要隐藏的系统属性（full / resetprop 模式生效），支持精确属性名、`*` / `?` 通配与 `re:` 正则
+    /// 例如 `ro.miui.*` 隐藏所有以 `ro.miui.` 开头的属性；同时出现在 props 中的属性以 props 为准
+    #[serde(default)]
+    pub hide_props: Option<Vec<String>>,
+    /// 厂商 ROM 预设（full / resetprop 模式生效），如 "hyperos"、"coloros"
+    /// 会写入该 ROM 的版本属性，并隐藏其他 ROM 的特征属性
+    #[serde(default)]
+    pub rom: Option<String>,
+    /// 覆盖 ROM 预设的版本号，如 "OS2.0"、"V15.0.0"
+    #[serde(default)]
+    pub rom_version: Option<String>,
+    /// 不需要展开的分区（full / resetprop 模式），可选值见 [`PARTITIONS`]
+    /// 默认会把 ro.product.* 与 ro.build.* 中的机型字段同步写入每个分区的同名属性
+    #[serde(default)]
+    pub exclude_partitions: Option<Vec<String>>,
+    /// 按分区单独指定的指纹，如 `{ vendor = "..." }`，未指定的分区使用 fingerprint
+    /// 写入 ro.<分区>.build.fingerprint（full / resetprop 模式），Build.getFingerprintedPartitions() 会读到它们
+    #[serde(default)]
+    pub partition_fingerprints: HashMap<String, String>,
+    /// 额外的系统属性（full / resetprop 模式生效），优先级高于由上述字段推导出的属性
+    /// 值为空字符串时表示将该属性设置为空
+    #[serde(default, deserialize_with = "prop_table")]
+    pub props: HashMap<String, String>,
+}
+
+impl DeviceProfile {
+    /// 编译需要隐藏的属性模式（hide_props 与 ROM 预设隐藏的属性），未设置时返回空集合
+    pub fn compile_hide_props(&self) -> Result<PatternSet> {
+        PatternSet::new(&self.hide_patterns())
+    }
+
+    /// hide_props 与 ROM 预设需要隐藏的属性模式
+    pub fn hide_patterns(&self) -> Vec<String> {
+        let mut patterns = self.hide_props.clone().unwrap_or_default();
+        if let Ok(Some(preset)) = self.rom_preset() {
+            patterns.extend(preset.hidden_markers().map(str::to_string));
+        }
+        patterns
+    }
+
+    /// 检查 exclude_partitions 与 partition_fingerprints 中的分区名是否有效
+    pub fn validate_partitions(&self) -> Result<()> {
+        let excluded = self.exclude_partitions.iter().flatten();
+        for partition in excluded.chain(self.partition_fingerprints.keys()) {
+            if !PARTITIONS.contains(&partition.as_str()) {
+                bail!(
+                    "Unknown partition '{partition}', available: {}",
+                    PARTITIONS.join(", ")
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// 查找 rom 对应的预设，未设置时返回 None，名称未知时报错
+    pub fn rom_preset(&self) -> Result<Option<&'static RomPreset>> {
+        let Some(rom) = self.rom.as_deref() else {
+            return Ok(None);
+        };
+        match ROM_PRESETS.iter().find(|preset| preset.name == rom) {
+            Some(preset) => Ok(Some(preset)),
+            None => {
+                let available: Vec<_> = ROM_PRESETS.iter().map(|preset| preset.name).collect();
+                bail!(
+                    "Unknown rom preset '{rom}', available: {}",
+                    available.join(", ")
+                )
+            }
+        }
+    }
+
+    /// 用 fallback 补全当前未设置的字段，已设置的字段保持不变
+    fn fill_from(&mut self, fallback: &DeviceProfile) {
+        fn fill<T: Clone>(field: &mut Option<T>, fallback: &Option<T>) {
+            if field.is_none() {
+                field.clone_from(fallback);
+            }
+        }
+
+        fill(&mut self.manufacturer, &fallback.manufacturer);
+        fill(&mut self.brand, &fallback.brand);
+        fill(&mut self.marketname, &fallback.marketname);
+        fill(&mut self.model, &fallback.model);
+        fill(&mut self.name, &fallback.name);
+        fill(&mut self.device, &fallback.device);
+        fill(&mut self.product, &fallback.product);
+        fill(&mut self.fingerprint, &fallback.fingerprint);
+        fill(&mut self.characteristics, &fallback.characteristics);
+        fill(&mut self.hardware, &fallback.hardware);
+        fill(&mut self.board, &fallback.board);
+        fill(&mut self.bootloader, &fallback.bootloader);
+        fill(&mut self.build_id, &fallback.build_id);
+        fill(&mut self.display, &fallback.display);
+        fill(&mut self.host, &fallback.host);
+        fill(&mut self.user, &fallback.user);
+        fill(&mut self.tags, &fallback.tags);
+        fill(&mut self.build_type, &fallback.build_type);
+        fill(&mut self.time, &fallback.time);
+        fill(&mut self.soc_manufacturer, &fallback.soc_manufacturer);
+        fill(&mut self.soc_model, &fallback.soc_model);
+        fill(&mut self.sku, &fallback.sku);
+        fill(&mut self.odm_sku, &fallback.odm_sku);
+        fill(&mut self.supported_abis, &fallback.supported_abis);
+        fill(
+            &mut self.supported_32_bit_abis,
+            &fallback.supported_32_bit_abis,
+        );
+        fill(
+            &mut self.supported_64_bit_abis,
+            &fallback.supported_64_bit_abis,
+        );
+        fill(
+            &mut self.force_denylist_unmount,
+            &fallback.force_denylist_unmount,
+        );
+        fill(&mut self.mode, &fallback.mode);
+        fill(&mut self.session_policy, &fallback.session_policy);
+        fill(&mut self.native_props, &fallback.native_props);
+        fill(&mut self.hide_props, &fallback.hide_props);
+        fill(&mut self.rom, &fallback.rom);
+        fill(&mut self.rom_version, &fallback.rom_version);
+        fill(&mut self.exclude_partitions, &fallback.exclude_partitions);
+        self.version.fill_from(&fallback.version);
+
+        for (partition, fingerprint) in &fallback.partition_fingerprints {
+            self.partition_fingerprints
+                .entry(partition.clone())
+                .or_insert_with(|| fingerprint.clone());
+        }
+        for (key, value) in &fallback.props {
+            self.props
+                .entry(key.clone())
+                .or_insert_with(|| value.clone());
+        }
+    }
+}
+
+/// resetprop 会话的还原策略
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionPolicy {
+    /// 应用所有进程退出后才还原
+    Exit,
+    /// 离开前台时还原，回到前台时重新伪装，进程退出后还原
+    #[default]
+    Foreground,
+    /// 不还原，直到重启
+    Reboot,
+}
+
+/// android.os.Build.VERSION 对应的系统版本信息
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct VersionProfile {
+    /// 系统版本号，如 "14"
+    #[serde(default)]
+    pub release: Option<String>,
+    /// API 等级，如 34
+    #[serde(default)]
+    pub sdk_int: Option<i32>,
+    /// 安全补丁日期，如 "2024-08-01"
+    #[serde(default)]
+    pub security_patch: Option<String>,
+    #[serde(default)]
+    pub incremental: Option<String>,
+    /// 正式版为 "REL"
+    #[serde(default)]
+    pub codename: Option<String>,
+}
+
+impl VersionProfile {
+    fn fill_from(&mut self, fallback: &VersionProfile) {
+        fn fill<T: Clone>(field: &mut Option<T>, fallback: &Option<T>) {
+            if field.is_none() {
+                field.clone_from(fallback);
+            }
+        }
+
+        fill(&mut self.release, &fallback.release);
+        fill(&mut self.sdk_int, &fallback.sdk_int);
+        fill(&mut self.security_patch, &fallback.security_patch);
+        fill(&mut self.incremental, &fallback.incremental);
+        fill(&mut self.codename, &fallback.codename);
+    }
+}
+
+/// 模板与 [[apps]] 共用的目标进程过滤条件（进程名、Android 用户）
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TargetFilter {
+    /// 进程名模式列表（语法同 packages），未设置时匹配该应用的所有进程
+    /// `:` 开头的模式只匹配进程名后缀，例如 `:game`、`!:sandboxed_process*`
+    #[serde(default)]
+    pub processes: Vec<String>,
+    #[serde(skip)]
+    process_patterns: PatternSet,
+    /// 只匹配这些 Android 用户 ID（0 为主用户，工作资料 / 应用分身通常为 10、999 等）
+    #[serde(default)]
+    pub users: Vec<u32>,
+    /// 排除这些 Android 用户 ID
+    #[serde(default)]
+    pub exclude_users: Vec<u32>,
+}
+
+impl TargetFilter {
+    fn compile(&mut self) -> Result<()> {
+        let patterns: Vec<String> = self
+            .processes
+            .iter()
+            .map(|pattern| expand_process_pattern(pattern))
+            .collect();
+        self.process_patterns = PatternSet::new(&patterns)?;
+        Ok(())
+    }
+
+    fn allows(&self, target: &TargetProcess) -> bool {
+        (self.users.is_empty() || self.users.contains(&target.user_id))
+            && !self.exclude_users.contains(&target.user_id)
+            && self.process_patterns.allows(&target.process)
+    }
+
+    /// 限定了范围的过滤条件数量，包名匹配程度相同时条件越多的规则越具体
+    fn constraint_count(&self) -> usize {
+        usize::from(self.process_patterns.has_include()) + usize::from(!self.users.is_empty())
+    }
+}
+
+/// 将 `:suffix` 形式的进程模式展开为匹配任意包名的通配模式
+fn expand_process_pattern(pattern: &str) -> String {
+    if let Some(excluded) = pattern.strip_prefix('!') {
+        return format!("!{}", expand_process_pattern(excluded));
+    }
+    if pattern.starts_with(':') {
+        format!("*{pattern}")
+    } else {
+        pattern.to_string()
+    }
+}
+
+/// 待匹配的目标进程
+#[derive(Debug, Clone)]
+pub struct TargetProcess {
+    /// 包名
+    pub package: String,
+    /// 完整进程名，例如 `com.tencent.mm:push`
+    pub process: String,
+    /// 应用 uid，uid 无效时为 0
+    pub uid: u32,
+    /// Android 用户 ID（uid / 100000）
+    pub user_id: u32,
+}
+
+/// 机型设备信息模板
+#[derive(Debug, Clone, Deserialize)]
+pub struct DeviceTemplate {
+    /// 包名列表，支持通配（`*`/`?`）、`re:` 正则以及 `!` 排除
+    #[serde(default)]
+    pub packages: Vec<String>,
+    #[serde(skip)]
+    package_patterns: PatternSet,
+    /// 进程过滤条件
+    #[serde(flatten)]
+    pub filter: TargetFilter,
+    /// 继承的父模板名称，未设置的字段从父模板继承（支持多级继承）
+    #[serde(default)]
+    pub extends: Option<String>,
+    /// 设备信息
+    #[serde(flatten)]
+    pub profile: DeviceProfile,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AppConfig {
+    /// 包名，可以是单个模式或模式列表（语法同模板的 packages）
+    #[serde(deserialize_with = "one_or_many")]
+    pub package: Vec<String>,
+    #[serde(skip)]
+    package_patterns: PatternSet,
+    /// 进程过滤条件
+    #[serde(flatten)]
+    pub filter: TargetFilter,
+    /// 显式引用的模板名称；未设置时使用 packages 列表包含该包名的模板
+    #[serde(default)]
+    pub template: Option<String>,
+    /// 直接指定设备信息，未设置的字段回退到模板
+    #[serde(flatten)]
+    pub profile: DeviceProfile,
 }
 
 #[derive(Debug, Deserialize)]
@@ -91,76 +389,283 @@ fn default_mode() -> String {
     "lite".to_string() // 默认使用轻量模式，增强隐蔽性
 }
 
+/// 解析 props 表：值可以是字符串、数字或布尔值；未加引号的点分键名
+/// （如 `ro.soc.model = "SM8650"`）会被 TOML 解析为嵌套表，这里重新拼接为完整属性名
+fn prop_table<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashMap<String, String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum PropValue {
+        String(String),
+        Integer(i64),
+        Float(f64),
+        Bool(bool),
+        Table(HashMap<String, PropValue>),
+    }
+
+    fn flatten(prefix: &str, table: HashMap<String, PropValue>, out: &mut HashMap<String, String>) {
+        for (key, value) in table {
+            let key = if prefix.is_empty() {
+                key
+            } else {
+                format!("{prefix}.{key}")
+            };
+            let value = match value {
+                PropValue::String(value) => value,
+                PropValue::Integer(value) => value.to_string(),
+                PropValue::Float(value) => value.to_string(),
+                PropValue::Bool(value) => value.to_string(),
+                PropValue::Table(table) => {
+                    flatten(&key, table, out);
+                    continue;
+                }
+            };
+            out.insert(key, value);
+        }
+    }
+
+    let mut props = HashMap::new();
+    flatten("", HashMap::deserialize(deserializer)?, &mut props);
+    Ok(props)
+}
+
+/// 规则命中的具体程度：先比较包名匹配，再比较过滤条件数量
+type MatchRank = (Specificity, usize);
+
+fn match_rank(
+    package_patterns: &PatternSet,
+    filter: &TargetFilter,
+    target: &TargetProcess,
+) -> Option<MatchRank> {
+    let specificity = package_patterns.best_match(&target.package)?;
+    filter
+        .allows(target)
+        .then(|| (specificity, filter.constraint_count()))
+}
+
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(value) => vec![value],
+        OneOrMany::Many(values) => values,
+    })
+}
+
 impl Config {
     pub fn from_toml(content: &str) -> Result<Self> {
-        Ok(toml::from_str(content)?)
-    }
-
-    /// 查找包名对应的应用配置（优先）或模板配置
-    pub fn get_app_config(&self, package_name: &str) -> Option<&AppConfig> {
-        self.apps.iter().find(|app| app.package == package_name)
-    }
-
-    /// 查找包名对应的模板（从模板的 packages 列表中查找）
-    pub fn find_template_for_package(&self, package_name: &str) -> Option<&DeviceTemplate> {
-        self.templates
-            .values()
-            .find(|template| template.packages.iter().any(|pkg| pkg == package_name))
-    }
-
-    /// 获取应用的最终配置（优先查找直接配置，其次查找模板的 packages 列表）
-    pub fn get_merged_config(&self, package_name: &str) -> Option<MergedAppConfig> {
-        // 优先查找直接配置的应用
-        if let Some(app) = self.get_app_config(package_name) {
-            return Some(MergedAppConfig {
-                manufacturer: app.manufacturer.clone(),
-                brand: app.brand.clone(),
-                marketname: app.marketname.clone(),
-                model: app.model.clone(),
-                name: app.name.clone(),
-                device: app.device.clone(),
-                product: app.product.clone(),
-                fingerprint: app.fingerprint.clone(),
-                characteristics: app.characteristics.clone(),
-                force_denylist_unmount: app
-                    .force_denylist_unmount
-                    .unwrap_or(self.default_force_denylist_unmount),
-                mode: app
-                    .mode
-                    .clone()
-                    .unwrap_or_else(|| self.default_mode.clone()),
-            });
+        let mut config: Config = toml::from_str(content)?;
+        config.resolve_template_inheritance()?;
+        config.validate_template_references()?;
+        config.compile_patterns()?;
+        Ok(config)
+    }
+
+    /// 展开所有模板的 extends 继承链，展开后的模板不再依赖父模板
+    fn resolve_template_inheritance(&mut self) -> Result<()> {
+        let mut names: Vec<&String> = self.templates.keys().collect();
+        names.sort();
+
+        let mut resolved = HashMap::with_capacity(self.templates.len());
+        for name in names {
+            self.resolve_template(name, &mut Vec::new(), &mut resolved)?;
+        }
+        self.templates = resolved;
+        Ok(())
+    }
+
+    /// 预编译模板与 [[apps]] 中的包名、进程名模式
+    fn compile_patterns(&mut self) -> Result<()> {
+        for (name, template) in &mut self.templates {
+            template.package_patterns = PatternSet::new(&template.packages)
+                .with_context(|| format!("Invalid packages in template '{name}'"))?;
+            template
+                .filter
+                .compile()
+                .with_context(|| format!("Invalid processes in template '{name}'"))?;
+            template
+                .profile
+                .rom_preset()
+                .with_context(|| format!("Invalid rom in template '{name}'"))?;
+            template
+                .profile
+                .validate_partitions()
+                .with_context(|| format!("Invalid partitions in template '{name}'"))?;
+            template
+                .profile
+                .compile_hide_props()
+                .with_context(|| format!("Invalid hide_props in template '{name}'"))?;
+        }
+        for app in &mut self.apps {
+            let label = app.package.join(", ");
+            app.package_patterns = PatternSet::new(&app.package)
+                .with_context(|| format!("Invalid package in app '{label}'"))?;
+            app.filter
+                .compile()
+                .with_context(|| format!("Invalid processes in app '{label}'"))?;
+            app.profile
+                .rom_preset()
+                .with_context(|| format!("Invalid rom in app '{label}'"))?;
+            app.profile
+                .validate_partitions()
+                .with_context(|| format!("Invalid partitions in app '{label}'"))?;
+            app.profile
+                .compile_hide_props()
+                .with_context(|| format!("Invalid hide_props in app '{label}'"))?;
         }
+        Ok(())
+    }
 
-        // 如果没有直接配置，查找模板的 packages 列表
-        if let Some(template) = self.find_template_for_package(package_name) {
-            return Some(MergedAppConfig {
-                manufacturer: template.manufacturer.clone(),
-                brand: template.brand.clone(),
-                marketname: template.marketname.clone(),
-                model: template.model.clone(),
-                name: template.name.clone(),
-                device: template.device.clone(),
-                product: template.product.clone(),
-                fingerprint: template.fingerprint.clone(),
-                characteristics: template.characteristics.clone(),
-                force_denylist_unmount: template
-                    .force_denylist_unmount
-                    .unwrap_or(self.default_force_denylist_unmount),
-                mode: template
-                    .mode
-                    .clone()
-                    .unwrap_or_else(|| self.default_mode.clone()),
-            });
+    /// 检查 [[apps]] 中显式引用的模板是否存在
+    fn validate_template_references(&self) -> Result<()> {
+        for app in &self.apps {
+            if let Some(template) = &app.template
+                && !self.templates.contains_key(template)
+            {
+                bail!(
+                    "App '{}' references unknown template '{template}'",
+                    app.package.join(", ")
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn resolve_template(
+        &self,
+        name: &str,
+        chain: &mut Vec<String>,
+        resolved: &mut HashMap<String, DeviceTemplate>,
+    ) -> Result<DeviceTemplate> {
+        if let Some(template) = resolved.get(name) {
+            return Ok(template.clone());
+        }
+
+        if chain.iter().any(|visited| visited == name) {
+            chain.push(name.to_string());
+            bail!(
+                "Template inheritance cycle detected: {}",
+                chain.join(" -> ")
+            );
+        }
+
+        let Some(template) = self.templates.get(name) else {
+            let parent = chain.last().cloned().unwrap_or_default();
+            chain.push(name.to_string());
+            bail!(
+                "Template '{parent}' extends unknown template '{name}' (chain: {})",
+                chain.join(" -> ")
+            );
+        };
+
+        // 只继承设备信息，packages 与过滤条件只作用于模板自身
+        let mut result = template.clone();
+        if let Some(parent_name) = &template.extends {
+            chain.push(name.to_string());
+            let parent = self.resolve_template(parent_name, chain, resolved)?;
+            chain.pop();
+            result.profile.fill_from(&parent.profile);
+        }
+
+        resolved.insert(name.to_string(), result.clone());
+        Ok(result)
+    }
+
+    /// 查找目标进程对应的应用配置
+    ///
+    /// 多个 [[apps]] 命中时选择最具体的一条：精确匹配 > 更长的模式 > 过滤条件更多 > 配置中靠前的条目
+    pub fn get_app_config(&self, target: &TargetProcess) -> Option<&AppConfig> {
+        let mut best: Option<(MatchRank, &AppConfig)> = None;
+        for app in &self.apps {
+            if let Some(rank) = match_rank(&app.package_patterns, &app.filter, target)
+                && best.is_none_or(|(current, _)| rank > current)
+            {
+                best = Some((rank, app));
+            }
+        }
+        best.map(|(_, app)| app)
+    }
+
+    /// 查找目标进程对应的模板（从模板的 packages 列表中查找）
+    ///
+    /// 多个模板命中时选择最具体的一条：精确匹配 > 更长的模式 > 过滤条件更多 > 模板名称字典序靠前
+    pub fn find_template_for_package(&self, target: &TargetProcess) -> Option<&DeviceTemplate> {
+        let mut best: Option<(MatchRank, &str, &DeviceTemplate)> = None;
+        for (name, template) in &self.templates {
+            if let Some(rank) = match_rank(&template.package_patterns, &template.filter, target)
+                && best.is_none_or(|(current, current_name, _)| {
+                    rank > current || (rank == current && name.as_str() < current_name)
+                })
+            {
+                best = Some((rank, name, template));
+            }
+        }
+        best.map(|(_, _, template)| template)
+    }
+
+    /// 获取应用的最终配置
+    ///
+    /// [[apps]] 中设置的字段优先，未设置的字段逐个回退到模板（显式 template 优先，
+    /// 其次是 packages 列表包含该包名的模板），模式与卸载选项最后回退到全局默认值
+    ///
+    /// 包名命中 [[apps]] 但进程或用户被其 processes / users / exclude_users 排除时，
+    /// 视为没有配置，不再回退到模板，否则模板会伪装本应跳过的进程
+    pub fn get_merged_config(&self, target: &TargetProcess) -> Option<MergedAppConfig> {
+        let app = self.get_app_config(target);
+        if app.is_none()
+            && self
+                .apps
+                .iter()
+                .any(|app| app.package_patterns.best_match(&target.package).is_some())
+        {
+            return None;
+        }
+        let template = match app.and_then(|app| app.template.as_deref()) {
+            Some(template_name) => self.templates.get(template_name),
+            None => self.find_template_for_package(target),
+        };
+
+        let mut profile = match (app, template) {
+            (None, None) => return None,
+            (Some(app), _) => app.profile.clone(),
+            (None, Some(_)) => DeviceProfile::default(),
+        };
+        if let Some(template) = template {
+            profile.fill_from(&template.profile);
         }
 
-        None
+        Some(MergedAppConfig {
+            force_denylist_unmount: profile
+                .force_denylist_unmount
+                .unwrap_or(self.default_force_denylist_unmount),
+            mode: profile
+                .mode
+                .clone()
+                .unwrap_or_else(|| self.default_mode.clone()),
+            native_props: profile.native_props.unwrap_or(false),
+            session_policy: profile.session_policy.unwrap_or_default(),
+            // 模式已在加载配置时校验过
+            hide_props: profile.compile_hide_props().unwrap_or_default(),
+            profile,
+        })
     }
 
     /// 构建合并配置的系统属性映射
     /// 注意：仅用于 full 模式的 SystemProperties Hook 和 resetprop 模式
-    /// 空字符串会被忽略，不会添加到映射中
-    pub fn build_merged_property_map(merged: &MergedAppConfig) -> HashMap<String, String> {
+    /// 由字段推导的属性会忽略空字符串；props 中显式设置的属性最后写入，
+    /// 会覆盖推导出的同名属性，且空字符串会被保留（用于将属性置空）
+    /// 同步到分区的属性只写入 property_exists 返回 true 的属性，避免凭空多出设备上没有的属性
+    pub fn build_merged_property_map(
+        merged: &MergedAppConfig,
+        property_exists: impl Fn(&str) -> bool,
+    ) -> HashMap<String, String> {
+        let merged = &merged.profile;
         let mut map = HashMap::new();
 
         if let Some(manufacturer) = &merged.manufacturer
@@ -214,22 +719,271 @@ impl Config {
             );
         }
 
+        let build_props = [
+            ("ro.hardware", &merged.hardware),
+            ("ro.product.board", &merged.board),
+            ("ro.bootloader", &merged.bootloader),
+            ("ro.build.id", &merged.build_id),
+            ("ro.build.display.id", &merged.display),
+            ("ro.build.host", &merged.host),
+            ("ro.build.user", &merged.user),
+            ("ro.build.tags", &merged.tags),
+            ("ro.build.type", &merged.build_type),
+            ("ro.soc.manufacturer", &merged.soc_manufacturer),
+            ("ro.soc.model", &merged.soc_model),
+            ("ro.boot.hardware.sku", &merged.sku),
+            ("ro.boot.product.hardware.sku", &merged.odm_sku),
+        ];
+        for (key, value) in build_props {
+            if let Some(value) = value
+                && !value.is_empty()
+            {
+                map.insert(key.to_string(), value.clone());
+            }
+        }
+
+        if let Some(time) = merged.time {
+            // Build.TIME = ro.build.date.utc * 1000
+            map.insert("ro.build.date.utc".to_string(), (time / 1000).to_string());
+        }
+
+        let abi_props = [
+            ("ro.product.cpu.abilist", &merged.supported_abis),
+            ("ro.product.cpu.abilist32", &merged.supported_32_bit_abis),
+            ("ro.product.cpu.abilist64", &merged.supported_64_bit_abis),
+        ];
+        for (key, abis) in abi_props {
+            if let Some(abis) = abis {
+                map.insert(key.to_string(), abis.join(","));
+            }
+        }
+
+        let version = &merged.version;
+        let version_props = [
+            ("ro.build.version.release", &version.release),
+            ("ro.build.version.security_patch", &version.security_patch),
+            ("ro.build.version.incremental", &version.incremental),
+            ("ro.build.version.codename", &version.codename),
+        ];
+        for (key, value) in version_props {
+            if let Some(value) = value
+                && !value.is_empty()
+            {
+                map.insert(key.to_string(), value.clone());
+            }
+        }
+        if let Some(sdk_int) = version.sdk_int {
+            map.insert("ro.build.version.sdk".to_string(), sdk_int.to_string());
+        }
+        // 正式版的 release_or_codename 与 release 相同，预览版则为 codename
+        let release_or_codename = match version.codename.as_deref() {
+            Some(codename) if !codename.is_empty() && codename != "REL" => Some(codename),
+            _ => version
+                .release
+                .as_deref()
+                .filter(|release| !release.is_empty()),
+        };
+        if let Some(value) = release_or_codename {
+            map.insert(
+                "ro.build.version.release_or_codename".to_string(),
+                value.to_string(),
+            );
+        }
+
+        if let Ok(Some(preset)) = merged.rom_preset() {
+            let version = merged
+                .rom_version
+                .as_deref()
+                .filter(|version| !version.is_empty())
+                .unwrap_or(preset.default_version);
+            for key in preset.version_props {
+                map.insert(key.to_string(), version.to_string());
+            }
+            for (key, value) in preset.props {
+                map.insert(key.to_string(), value.to_string());
+            }
+        }
+
+        for (key, value) in &merged.props {
+            map.insert(key.clone(), value.clone());
+        }
+
+        Self::expand_partition_props(&mut map, merged, property_exists);
         map
     }
+
+    /// 将 ro.product.* 与 ro.build.* 的值同步到各分区的同名属性，
+    /// 如 ro.product.vendor.model、ro.vendor.build.fingerprint；props 中显式写出的分区属性保持不变
+    fn expand_partition_props(
+        map: &mut HashMap<String, String>,
+        profile: &DeviceProfile,
+        property_exists: impl Fn(&str) -> bool,
+    ) {
+        let excluded = profile.exclude_partitions.as_deref().unwrap_or_default();
+        let mut expanded = Vec::new();
+
+        for partition in PARTITIONS {
+            if excluded.iter().any(|excluded| excluded == partition) {
+                continue;
+            }
+            let product_keys = PARTITION_PRODUCT_KEYS.iter().map(|key| {
+                (
+                    format!("ro.product.{key}"),
+                    format!("ro.product.{partition}.{key}"),
+                )
+            });
+            let build_keys = PARTITION_BUILD_KEYS.iter().map(|key| {
+                (
+                    format!("ro.build.{key}"),
+                    format!("ro.{partition}.build.{key}"),
+                )
+            });
+            for (source, target) in product_keys.chain(build_keys) {
+                if profile.props.contains_key(&target) || !property_exists(&target) {
+                    continue;
+                }
+                if let Some(value) = map.get(&source) {
+                    expanded.push((target, value.clone()));
+                }
+            }
+        }
+
+        // 单独指定的分区指纹优先于由 fingerprint 同步的值
+        for (partition, fingerprint) in &profile.partition_fingerprints {
+            let key = format!("ro.{partition}.build.fingerprint");
+            if !fingerprint.is_empty() && !profile.props.contains_key(&key) && property_exists(&key)
+            {
+                expanded.push((key, fingerprint.clone()));
+            }
+        }
+
+        map.extend(expanded);
+    }
+}
+
+/// 需要同步机型信息的分区
+pub const PARTITIONS: &[&str] = &[
+    "system",
+    "system_ext",
+    "vendor",
+    "odm",
+    "product",
+    "bootimage",
+];
+
+/// 按分区展开的 ro.product.<分区>.* 属性
+const PARTITION_PRODUCT_KEYS: &[&str] = &[
+    "brand",
+    "device",
+    "manufacturer",
+    "marketname",
+    "model",
+    "name",
+];
+
+/// 按分区展开的 ro.<分区>.build.* 属性
+const PARTITION_BUILD_KEYS: &[&str] = &[
+    "fingerprint",
+    "id",
+    "tags",
+    "type",
+    "date.utc",
+    "version.incremental",
+    "version.release",
+    "version.release_or_codename",
+    "version.sdk",
+];
+
+/// 厂商 ROM 预设：该 ROM 特有的版本属性，以及需要隐藏的其他 ROM 特征属性
+pub struct RomPreset {
+    pub name: &'static str,
+    /// 写入 rom_version（或默认版本号）的属性
+    pub version_props: &'static [&'static str],
+    pub default_version: &'static str,
+    /// 固定值的附加属性
+    pub props: &'static [(&'static str, &'static str)],
+}
+
+impl RomPreset {
+    /// 不属于本 ROM 的特征属性，应用该预设时需要隐藏
+    fn hidden_markers(&self) -> impl Iterator<Item = &'static str> {
+        let own_keys: Vec<&str> = self
+            .version_props
+            .iter()
+            .copied()
+            .chain(self.props.iter().map(|(key, _)| *key))
+            .collect();
+        ROM_MARKERS.iter().copied().filter(move |marker| {
+            !own_keys
+                .iter()
+                .any(|key| glob_match(marker.as_bytes(), key.as_bytes()))
+        })
+    }
 }
 
-/// 合并后的应用配置（模板 + 直接配置）
+/// 各厂商 ROM 的特征属性，应用预设时会隐藏不属于该 ROM 的部分
+const ROM_MARKERS: &[&str] = &[
+    "ro.miui.*",
+    "ro.mi.os.*",
+    "ro.build.version.opporom",
+    "ro.build.version.oplusrom*",
+    "ro.vivo.*",
+    "ro.build.version.magic",
+    "ro.build.version.emui",
+    "ro.build.hw_emui_api_level",
+    "ro.build.version.oneui",
+];
+
+const ROM_PRESETS: &[RomPreset] = &[
+    RomPreset {
+        name: "hyperos",
+        version_props: &["ro.mi.os.version.name"],
+        default_version: "OS2.0",
+        props: &[
+            ("ro.mi.os.version.code", "2"),
+            ("ro.miui.ui.version.name", "V816"),
+            ("ro.miui.ui.version.code", "816"),
+        ],
+    },
+    RomPreset {
+        name: "miui",
+        version_props: &["ro.miui.ui.version.name"],
+        default_version: "V140",
+        props: &[("ro.miui.ui.version.code", "14")],
+    },
+    RomPreset {
+        name: "coloros",
+        version_props: &["ro.build.version.opporom", "ro.build.version.oplusrom"],
+        default_version: "V15.0.0",
+        props: &[],
+    },
+    RomPreset {
+        name: "originos",
+        version_props: &["ro.vivo.os.version"],
+        default_version: "5.0",
+        props: &[("ro.vivo.os.name", "OriginOS")],
+    },
+    RomPreset {
+        name: "magicos",
+        version_props: &["ro.build.version.magic"],
+        default_version: "9.0.0",
+        props: &[],
+    },
+    RomPreset {
+        name: "oneui",
+        version_props: &["ro.build.version.oneui"],
+        default_version: "70000",
+        props: &[],
+    },
+];
+
+/// 合并后的应用配置（[[apps]] 字段逐个覆盖模板字段）
 #[derive(Debug, Clone)]
 pub struct MergedAppConfig {
-    pub manufacturer: Option<String>,
-    pub brand: Option<String>,
-    pub marketname: Option<String>,
-    pub model: Option<String>,
-    pub name: Option<String>,
-    pub device: Option<String>,
-    pub product: Option<String>,
-    pub fingerprint: Option<String>,
-    pub characteristics: Option<String>,
+    pub profile: DeviceProfile,
     pub force_denylist_unmount: bool,
     pub mode: String,
+    pub native_props: bool,
+    pub session_policy: SessionPolicy,
+    pub hide_props: PatternSet,
 }
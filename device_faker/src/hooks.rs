@@ -1,56 +1,205 @@
 use anyhow::Context;
 use jni::JNIEnv;
-use jni::objects::{JClass, JString, JValue};
+use jni::objects::{JClass, JObject, JStaticFieldID, JValue};
+use jni::signature::{JavaType, Primitive};
 use jni::strings::JNIStr;
 use jni::sys::JNINativeMethod;
+use serde::{Deserialize, Serialize};
 
 use crate::config::MergedAppConfig;
-use crate::state::{FAKE_PROPS, ORIGINAL_NATIVE_GET, OriginalNativeGet};
+use crate::state::{ORIGINAL_NATIVES, OriginalNatives, PROP_SNAPSHOT, PropEntry, PropOverride};
 use zygisk_api::api::{V4, ZygiskApi};
 
+/// Build 字段的写入结果，debug 模式下输出到日志，并通过 companion 写入状态文件
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct BuildHookReport {
+    /// 写入后回读一致的字段
+    pub applied: Vec<String>,
+    /// 当前系统中不存在的字段（如旧系统没有 SOC_MODEL）
+    pub skipped: Vec<String>,
+    /// 写入失败或回读不一致的字段
+    pub failed: Vec<FieldFailure>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FieldFailure {
+    pub field: String,
+    pub error: String,
+}
+
+/// 单个字段的处理结果
+enum FieldOutcome {
+    Applied,
+    Missing,
+}
+
+impl BuildHookReport {
+    /// 记录单个字段的结果；失败时清除挂起的 JNI 异常，保证后续字段仍可继续写入
+    fn record(&mut self, env: &JNIEnv, field: String, result: anyhow::Result<FieldOutcome>) {
+        match result {
+            Ok(FieldOutcome::Applied) => self.applied.push(field),
+            Ok(FieldOutcome::Missing) => self.skipped.push(field),
+            Err(err) => {
+                let _ = env.exception_clear();
+                self.failed.push(FieldFailure {
+                    field,
+                    error: format!("{err:#}"),
+                });
+            }
+        }
+    }
+}
+
 /// 根据合并配置 Hook android.os.Build 的静态字段。
-pub fn hook_build_fields(env: &mut JNIEnv, merged_config: &MergedAppConfig) -> anyhow::Result<()> {
+///
+/// 单个字段失败不会中断其余字段，每个字段写入后都会回读校验，结果汇总在返回的报告中。
+pub fn hook_build_fields(
+    env: &mut JNIEnv,
+    merged_config: &MergedAppConfig,
+) -> anyhow::Result<BuildHookReport> {
+    let profile = &merged_config.profile;
     let build_class = env
         .find_class("android/os/Build")
         .context("Failed to find Build class")?;
+    let mut report = BuildHookReport::default();
 
-    if let Some(manufacturer) = &merged_config.manufacturer
-        && !manufacturer.is_empty()
-    {
-        set_build_field(env, &build_class, "MANUFACTURER", manufacturer)?;
+    let string_fields = [
+        ("MANUFACTURER", &profile.manufacturer),
+        ("BRAND", &profile.brand),
+        ("MODEL", &profile.model),
+        ("DEVICE", &profile.device),
+        ("PRODUCT", &profile.product),
+        ("FINGERPRINT", &profile.fingerprint),
+        ("HARDWARE", &profile.hardware),
+        ("BOARD", &profile.board),
+        ("BOOTLOADER", &profile.bootloader),
+        ("ID", &profile.build_id),
+        ("DISPLAY", &profile.display),
+        ("HOST", &profile.host),
+        ("USER", &profile.user),
+        ("TAGS", &profile.tags),
+        ("TYPE", &profile.build_type),
+        ("SOC_MANUFACTURER", &profile.soc_manufacturer),
+        ("SOC_MODEL", &profile.soc_model),
+        ("SKU", &profile.sku),
+        ("ODM_SKU", &profile.odm_sku),
+    ];
+    for (field_name, value) in string_fields {
+        if let Some(value) = value
+            && !value.is_empty()
+        {
+            let result = set_build_field(env, &build_class, field_name, value);
+            report.record(env, format!("Build.{field_name}"), result);
+        }
     }
 
-    if let Some(brand) = &merged_config.brand
-        && !brand.is_empty()
-    {
-        set_build_field(env, &build_class, "BRAND", brand)?;
+    if let Some(time) = profile.time {
+        let result = set_build_long_field(env, &build_class, "TIME", time);
+        report.record(env, "Build.TIME".to_string(), result);
     }
 
-    if let Some(model) = &merged_config.model
-        && !model.is_empty()
-    {
-        set_build_field(env, &build_class, "MODEL", model)?;
+    let array_fields = [
+        ("SUPPORTED_ABIS", &profile.supported_abis),
+        ("SUPPORTED_32_BIT_ABIS", &profile.supported_32_bit_abis),
+        ("SUPPORTED_64_BIT_ABIS", &profile.supported_64_bit_abis),
+    ];
+    for (field_name, values) in array_fields {
+        if let Some(values) = values {
+            let result = set_build_string_array_field(env, &build_class, field_name, values);
+            report.record(env, format!("Build.{field_name}"), result);
+        }
     }
 
-    if let Some(device) = &merged_config.device
-        && !device.is_empty()
-    {
-        set_build_field(env, &build_class, "DEVICE", device)?;
+    hook_build_version_fields(env, merged_config, &mut report);
+
+    Ok(report)
+}
+
+/// 根据 version 子表 Hook android.os.Build$VERSION 的静态字段。
+fn hook_build_version_fields(
+    env: &mut JNIEnv,
+    merged_config: &MergedAppConfig,
+    report: &mut BuildHookReport,
+) {
+    let version = &merged_config.profile.version;
+    let string_fields = [
+        ("RELEASE", &version.release),
+        ("SECURITY_PATCH", &version.security_patch),
+        ("INCREMENTAL", &version.incremental),
+        ("CODENAME", &version.codename),
+    ];
+    let has_string_field = string_fields
+        .iter()
+        .any(|(_, value)| value.as_deref().is_some_and(|value| !value.is_empty()));
+    if !has_string_field && version.sdk_int.is_none() {
+        return;
     }
 
-    if let Some(product) = &merged_config.product
-        && !product.is_empty()
-    {
-        set_build_field(env, &build_class, "PRODUCT", product)?;
+    let version_class = match env.find_class("android/os/Build$VERSION") {
+        Ok(class) => class,
+        Err(err) => {
+            let result = Err(anyhow::Error::new(err).context("Failed to find Build$VERSION class"));
+            report.record(env, "Build$VERSION".to_string(), result);
+            return;
+        }
+    };
+
+    for (field_name, value) in string_fields {
+        if let Some(value) = value
+            && !value.is_empty()
+        {
+            let result = set_build_field(env, &version_class, field_name, value);
+            report.record(env, format!("Build.VERSION.{field_name}"), result);
+        }
     }
 
-    if let Some(fingerprint) = &merged_config.fingerprint
-        && !fingerprint.is_empty()
-    {
-        set_build_field(env, &build_class, "FINGERPRINT", fingerprint)?;
+    if let Some(sdk_int) = version.sdk_int {
+        let result = set_build_int_field(env, &version_class, "SDK_INT", sdk_int);
+        report.record(env, "Build.VERSION.SDK_INT".to_string(), result);
+        // 已废弃的 Build.VERSION.SDK 仍被部分旧应用读取
+        let result = set_build_field(env, &version_class, "SDK", &sdk_int.to_string());
+        report.record(env, "Build.VERSION.SDK".to_string(), result);
     }
+}
 
-    Ok(())
+/// 查找静态字段，字段不存在时清除 NoSuchFieldError 并返回 None
+fn find_static_field(
+    env: &mut JNIEnv,
+    class: &JClass,
+    field_name: &str,
+    signature: &str,
+) -> anyhow::Result<Option<JStaticFieldID>> {
+    match env.get_static_field_id(class, field_name, signature) {
+        Ok(field_id) => Ok(Some(field_id)),
+        Err(jni::errors::Error::JavaException) => {
+            env.exception_clear()
+                .context("Failed to clear NoSuchFieldError")?;
+            Ok(None)
+        }
+        Err(err) => {
+            Err(anyhow::Error::new(err).context(format!("Failed to get field ID for {field_name}")))
+        }
+    }
+}
+
+fn string_type() -> JavaType {
+    JavaType::Object("java/lang/String".to_string())
+}
+
+/// 对象字段回读后应与写入的对象为同一引用
+fn verify_same_object(
+    env: &JNIEnv,
+    current: &JObject,
+    expected: &JObject,
+    field_name: &str,
+) -> anyhow::Result<FieldOutcome> {
+    let same = env
+        .is_same_object(current, expected)
+        .with_context(|| format!("Failed to compare field {field_name}"))?;
+    if !same {
+        anyhow::bail!("Readback mismatch for {field_name}");
+    }
+    Ok(FieldOutcome::Applied)
 }
 
 fn set_build_field(
@@ -58,10 +207,11 @@ fn set_build_field(
     build_class: &JClass,
     field_name: &str,
     value: &str,
-) -> anyhow::Result<()> {
-    let field_id = env
-        .get_static_field_id(build_class, field_name, "Ljava/lang/String;")
-        .with_context(|| format!("Failed to get field ID for {field_name}"))?;
+) -> anyhow::Result<FieldOutcome> {
+    let Some(field_id) = find_static_field(env, build_class, field_name, "Ljava/lang/String;")?
+    else {
+        return Ok(FieldOutcome::Missing);
+    };
 
     let new_value = env
         .new_string(value)
@@ -70,29 +220,298 @@ fn set_build_field(
     env.set_static_field(build_class, field_id, JValue::Object(&new_value))
         .with_context(|| format!("Failed to set field {field_name}"))?;
 
-    Ok(())
+    let current = env
+        .get_static_field_unchecked(build_class, field_id, string_type())
+        .and_then(|value| value.l())
+        .with_context(|| format!("Failed to read back field {field_name}"))?;
+    verify_same_object(env, &current, &new_value, field_name)
+}
+
+fn set_build_int_field(
+    env: &mut JNIEnv,
+    build_class: &JClass,
+    field_name: &str,
+    value: i32,
+) -> anyhow::Result<FieldOutcome> {
+    let Some(field_id) = find_static_field(env, build_class, field_name, "I")? else {
+        return Ok(FieldOutcome::Missing);
+    };
+
+    env.set_static_field(build_class, field_id, JValue::Int(value))
+        .with_context(|| format!("Failed to set field {field_name}"))?;
+
+    let current = env
+        .get_static_field_unchecked(build_class, field_id, JavaType::Primitive(Primitive::Int))
+        .and_then(|value| value.i())
+        .with_context(|| format!("Failed to read back field {field_name}"))?;
+    if current != value {
+        anyhow::bail!("Readback mismatch for {field_name}: expected {value}, got {current}");
+    }
+    Ok(FieldOutcome::Applied)
+}
+
+fn set_build_long_field(
+    env: &mut JNIEnv,
+    build_class: &JClass,
+    field_name: &str,
+    value: i64,
+) -> anyhow::Result<FieldOutcome> {
+    let Some(field_id) = find_static_field(env, build_class, field_name, "J")? else {
+        return Ok(FieldOutcome::Missing);
+    };
+
+    env.set_static_field(build_class, field_id, JValue::Long(value))
+        .with_context(|| format!("Failed to set field {field_name}"))?;
+
+    let current = env
+        .get_static_field_unchecked(build_class, field_id, JavaType::Primitive(Primitive::Long))
+        .and_then(|value| value.j())
+        .with_context(|| format!("Failed to read back field {field_name}"))?;
+    if current != value {
+        anyhow::bail!("Readback mismatch for {field_name}: expected {value}, got {current}");
+    }
+    Ok(FieldOutcome::Applied)
+}
+
+fn set_build_string_array_field(
+    env: &mut JNIEnv,
+    build_class: &JClass,
+    field_name: &str,
+    values: &[String],
+) -> anyhow::Result<FieldOutcome> {
+    let Some(field_id) = find_static_field(env, build_class, field_name, "[Ljava/lang/String;")?
+    else {
+        return Ok(FieldOutcome::Missing);
+    };
+
+    let array = env
+        .new_object_array(values.len() as i32, "java/lang/String", JObject::null())
+        .with_context(|| format!("Failed to create array for {field_name}"))?;
+    for (index, value) in values.iter().enumerate() {
+        let element = env
+            .new_string(value)
+            .with_context(|| format!("Failed to create string for {value}"))?;
+        env.set_object_array_element(&array, index as i32, element)
+            .with_context(|| format!("Failed to fill array for {field_name}"))?;
+    }
+
+    env.set_static_field(build_class, field_id, JValue::Object(&array))
+        .with_context(|| format!("Failed to set field {field_name}"))?;
+
+    let current = env
+        .get_static_field_unchecked(
+            build_class,
+            field_id,
+            JavaType::Array(Box::new(string_type())),
+        )
+        .and_then(|value| value.l())
+        .with_context(|| format!("Failed to read back field {field_name}"))?;
+    verify_same_object(env, &current, &array, field_name)
 }
 
-/// Hook SystemProperties.native_get 以截获属性查询。
-pub fn hook_system_properties(api: &mut ZygiskApi<V4>, env: &JNIEnv) -> anyhow::Result<()> {
-    let mut methods = [JNINativeMethod {
-        name: c"native_get".as_ptr() as *mut u8,
-        signature: c"(Ljava/lang/String;Ljava/lang/String;)Ljava/lang/String;".as_ptr() as *mut u8,
-        fnPtr: native_get_hook as *mut std::ffi::c_void,
-    }];
+/// SystemProperties Hook 的安装结果
+pub struct SystemPropertiesHookReport {
+    /// 已替换并保存了原函数的方法
+    pub hooked: Vec<&'static str>,
+    /// 当前系统中不存在的方法（如旧系统没有 Handle 相关方法）
+    pub missing: Vec<&'static str>,
+    /// 注册失败、仍指向替身实现的方法
+    pub failed: Vec<&'static str>,
+}
+
+/// 与 hook_system_properties 中 methods 数组一一对应的方法描述，用于日志
+const SYSTEM_PROPERTIES_METHODS: [&str; 9] = [
+    "native_get(String, String)",
+    "native_get_int(String, int)",
+    "native_get_long(String, long)",
+    "native_get_boolean(String, boolean)",
+    "native_find(String)",
+    "native_get(long)",
+    "native_get_int(long, int)",
+    "native_get_long(long, long)",
+    "native_get_boolean(long, boolean)",
+];
+
+/// Hook SystemProperties 的 native_get 系列方法以截获属性查询。
+///
+/// 一个方法都没有 Hook 成功时返回错误，此时不会保存任何原函数。
+pub fn hook_system_properties(
+    api: &mut ZygiskApi<V4>,
+    env: &JNIEnv,
+) -> anyhow::Result<SystemPropertiesHookReport> {
+    let mut methods = [
+        JNINativeMethod {
+            name: c"native_get".as_ptr() as *mut u8,
+            signature: c"(Ljava/lang/String;Ljava/lang/String;)Ljava/lang/String;".as_ptr()
+                as *mut u8,
+            fnPtr: native_get_hook as *mut std::ffi::c_void,
+        },
+        JNINativeMethod {
+            name: c"native_get_int".as_ptr() as *mut u8,
+            signature: c"(Ljava/lang/String;I)I".as_ptr() as *mut u8,
+            fnPtr: native_get_int_hook as *mut std::ffi::c_void,
+        },
+        JNINativeMethod {
+            name: c"native_get_long".as_ptr() as *mut u8,
+            signature: c"(Ljava/lang/String;J)J".as_ptr() as *mut u8,
+            fnPtr: native_get_long_hook as *mut std::ffi::c_void,
+        },
+        JNINativeMethod {
+            name: c"native_get_boolean".as_ptr() as *mut u8,
+            signature: c"(Ljava/lang/String;Z)Z".as_ptr() as *mut u8,
+            fnPtr: native_get_boolean_hook as *mut std::ffi::c_void,
+        },
+        JNINativeMethod {
+            name: c"native_find".as_ptr() as *mut u8,
+            signature: c"(Ljava/lang/String;)J".as_ptr() as *mut u8,
+            fnPtr: native_find_hook as *mut std::ffi::c_void,
+        },
+        JNINativeMethod {
+            name: c"native_get".as_ptr() as *mut u8,
+            signature: c"(J)Ljava/lang/String;".as_ptr() as *mut u8,
+            fnPtr: handle_get_hook as *mut std::ffi::c_void,
+        },
+        JNINativeMethod {
+            name: c"native_get_int".as_ptr() as *mut u8,
+            signature: c"(JI)I".as_ptr() as *mut u8,
+            fnPtr: handle_get_int_hook as *mut std::ffi::c_void,
+        },
+        JNINativeMethod {
+            name: c"native_get_long".as_ptr() as *mut u8,
+            signature: c"(JJ)J".as_ptr() as *mut u8,
+            fnPtr: handle_get_long_hook as *mut std::ffi::c_void,
+        },
+        JNINativeMethod {
+            name: c"native_get_boolean".as_ptr() as *mut u8,
+            signature: c"(JZ)Z".as_ptr() as *mut u8,
+            fnPtr: handle_get_boolean_hook as *mut std::ffi::c_void,
+        },
+    ];
 
     let class_name = unsafe { JNIStr::from_ptr(c"android/os/SystemProperties".as_ptr()) };
 
+    let replacements = methods.map(|method| method.fnPtr);
     unsafe {
         api.hook_jni_native_methods(env.unsafe_clone(), class_name, &mut methods);
     }
 
-    let original_fn_ptr = unsafe {
-        std::mem::transmute::<*mut std::ffi::c_void, OriginalNativeGet>(methods[0].fnPtr)
+    // 方法不存在时 fnPtr 被置空；找不到类或注册失败时 fnPtr 保持为替身实现，
+    // 这两种情况都不能保存为原函数，否则调用原函数会无限递归
+    let mut report = SystemPropertiesHookReport {
+        hooked: Vec::new(),
+        missing: Vec::new(),
+        failed: Vec::new(),
     };
-    *ORIGINAL_NATIVE_GET.lock().unwrap() = Some(original_fn_ptr);
+    for ((method, replacement), label) in methods
+        .iter_mut()
+        .zip(replacements)
+        .zip(SYSTEM_PROPERTIES_METHODS)
+    {
+        if method.fnPtr.is_null() {
+            report.missing.push(label);
+        } else if method.fnPtr == replacement {
+            report.failed.push(label);
+            method.fnPtr = std::ptr::null_mut();
+        } else {
+            report.hooked.push(label);
+        }
+    }
+    if report.hooked.is_empty() {
+        anyhow::bail!(
+            "Failed to hook SystemProperties natives (failed: [{}], missing: [{}])",
+            report.failed.join(", "),
+            report.missing.join(", ")
+        );
+    }
 
-    Ok(())
+    let [
+        get,
+        get_int,
+        get_long,
+        get_boolean,
+        find,
+        handle_get,
+        handle_get_int,
+        handle_get_long,
+        handle_get_boolean,
+    ] = methods.map(|method| method.fnPtr);
+    let originals = unsafe {
+        OriginalNatives {
+            native_get: to_original(get),
+            native_get_int: to_original(get_int),
+            native_get_long: to_original(get_long),
+            native_get_boolean: to_original(get_boolean),
+            native_find: to_original(find),
+            handle_get: to_original(handle_get),
+            handle_get_int: to_original(handle_get_int),
+            handle_get_long: to_original(handle_get_long),
+            handle_get_boolean: to_original(handle_get_boolean),
+        }
+    };
+    if ORIGINAL_NATIVES.set(originals).is_err() {
+        anyhow::bail!("SystemProperties natives already hooked");
+    }
+
+    Ok(report)
+}
+
+/// 将 Hook 返回的原函数地址转换为函数指针，地址为空时返回 None
+///
+/// 调用方需保证 `T` 是与该地址匹配的函数指针类型。
+unsafe fn to_original<T: Copy>(fn_ptr: *mut std::ffi::c_void) -> Option<T> {
+    (!fn_ptr.is_null()).then(|| unsafe { std::mem::transmute_copy(&fn_ptr) })
+}
+
+fn originals() -> Option<&'static OriginalNatives> {
+    ORIGINAL_NATIVES.get()
+}
+
+/// 属性名缓冲区长度，更长的属性名视为不需要伪装
+const PROP_KEY_BUFFER_LEN: usize = 256;
+
+/// 把 key 复制到栈上的缓冲区后查询伪装 / 隐藏规则，不加锁、不分配内存
+///
+/// ART 内部以 Latin-1 / UTF-16 保存字符串，GetStringUTFChars 每次都会分配新的副本，
+/// 因此改用 GetStringUTFRegion 直接写入固定缓冲区。
+fn lookup_override(
+    env: *mut jni::sys::JNIEnv,
+    key: jni::sys::jstring,
+) -> Option<PropOverride<'static>> {
+    let snapshot = PROP_SNAPSHOT.get()?;
+    if env.is_null() || key.is_null() {
+        return None;
+    }
+
+    unsafe {
+        let functions = &**env;
+        let get_length = functions.GetStringLength?;
+        let get_utf_length = functions.GetStringUTFLength?;
+        let get_utf_region = functions.GetStringUTFRegion?;
+
+        let utf_length = usize::try_from(get_utf_length(env, key)).ok()?;
+        // 预留结尾 NUL 的位置
+        if utf_length >= PROP_KEY_BUFFER_LEN {
+            return None;
+        }
+        let mut buffer = [0 as std::ffi::c_char; PROP_KEY_BUFFER_LEN];
+        get_utf_region(env, key, 0, get_length(env, key), buffer.as_mut_ptr());
+        let key = std::slice::from_raw_parts(buffer.as_ptr().cast::<u8>(), utf_length);
+        snapshot.lookup(key)
+    }
+}
+
+/// 返回伪装值对应的 Java 字符串，优先使用快照中缓存的全局引用
+fn fake_java_string(env: *mut jni::sys::JNIEnv, entry: &PropEntry) -> jni::sys::jstring {
+    if let Some(java_value) = &entry.java_value {
+        return java_value.as_obj().as_raw();
+    }
+    if env.is_null() {
+        return std::ptr::null_mut();
+    }
+    match unsafe { (**env).NewStringUTF } {
+        Some(new_string) => unsafe { new_string(env, entry.value.as_ptr()) },
+        None => std::ptr::null_mut(),
+    }
 }
 
 /// 为 Hook 提供的 SystemProperties.native_get 替身实现。
@@ -102,40 +521,252 @@ pub unsafe extern "C" fn native_get_hook(
     key: jni::sys::jstring,
     def: jni::sys::jstring,
 ) -> jni::sys::jstring {
-    let mut env_wrapper = match unsafe { JNIEnv::from_raw(env) } {
-        Ok(e) => e,
-        Err(_) => return def,
-    };
+    match lookup_override(env, key) {
+        Some(PropOverride::Fake { entry, .. }) => {
+            let fake_result = fake_java_string(env, entry);
+            if !fake_result.is_null() {
+                return fake_result;
+            }
+        }
+        Some(PropOverride::Hidden) => return def,
+        None => {}
+    }
+
+    if let Some(orig_fn) = originals().and_then(|originals| originals.native_get) {
+        return unsafe { orig_fn(env, class, key, def) };
+    }
+
+    def
+}
+
+/// 为 Hook 提供的 SystemProperties.native_get_int 替身实现。
+pub unsafe extern "C" fn native_get_int_hook(
+    env: *mut jni::sys::JNIEnv,
+    class: jni::sys::jclass,
+    key: jni::sys::jstring,
+    def: jni::sys::jint,
+) -> jni::sys::jint {
+    match lookup_override(env, key) {
+        Some(PropOverride::Fake { entry, .. }) => return fake_int(entry, def),
+        Some(PropOverride::Hidden) => return def,
+        None => {}
+    }
+
+    if let Some(orig_fn) = originals().and_then(|originals| originals.native_get_int) {
+        return unsafe { orig_fn(env, class, key, def) };
+    }
+
+    def
+}
+
+/// 为 Hook 提供的 SystemProperties.native_get_long 替身实现。
+pub unsafe extern "C" fn native_get_long_hook(
+    env: *mut jni::sys::JNIEnv,
+    class: jni::sys::jclass,
+    key: jni::sys::jstring,
+    def: jni::sys::jlong,
+) -> jni::sys::jlong {
+    match lookup_override(env, key) {
+        Some(PropOverride::Fake { entry, .. }) => return fake_long(entry, def),
+        Some(PropOverride::Hidden) => return def,
+        None => {}
+    }
+
+    if let Some(orig_fn) = originals().and_then(|originals| originals.native_get_long) {
+        return unsafe { orig_fn(env, class, key, def) };
+    }
+
+    def
+}
+
+/// 为 Hook 提供的 SystemProperties.native_get_boolean 替身实现。
+pub unsafe extern "C" fn native_get_boolean_hook(
+    env: *mut jni::sys::JNIEnv,
+    class: jni::sys::jclass,
+    key: jni::sys::jstring,
+    def: jni::sys::jboolean,
+) -> jni::sys::jboolean {
+    match lookup_override(env, key) {
+        Some(PropOverride::Fake { entry, .. }) => return fake_boolean(entry, def),
+        Some(PropOverride::Hidden) => return def,
+        None => {}
+    }
+
+    if let Some(orig_fn) = originals().and_then(|originals| originals.native_get_boolean) {
+        return unsafe { orig_fn(env, class, key, def) };
+    }
+
+    def
+}
+
+fn fake_int(entry: &PropEntry, def: jni::sys::jint) -> jni::sys::jint {
+    entry
+        .value
+        .to_str()
+        .ok()
+        .and_then(parse_android_int)
+        .and_then(|value| jni::sys::jint::try_from(value).ok())
+        .unwrap_or(def)
+}
+
+fn fake_long(entry: &PropEntry, def: jni::sys::jlong) -> jni::sys::jlong {
+    entry
+        .value
+        .to_str()
+        .ok()
+        .and_then(parse_android_int)
+        .unwrap_or(def)
+}
+
+fn fake_boolean(entry: &PropEntry, def: jni::sys::jboolean) -> jni::sys::jboolean {
+    match entry.value.to_str().ok().and_then(parse_android_bool) {
+        Some(true) => jni::sys::JNI_TRUE,
+        Some(false) => jni::sys::JNI_FALSE,
+        None => def,
+    }
+}
+
+/// 伪装句柄的标记位：真实句柄是属性区中的 prop_info 地址，用户态地址不会用到第 48~55 位
+const FAKE_HANDLE_TAG: jni::sys::jlong = 0xDF << 48;
+const FAKE_HANDLE_TAG_MASK: jni::sys::jlong = 0xFF << 48;
 
-    let key_jstring = unsafe { JString::from_raw(key) };
-    let key_str = match env_wrapper.get_string(&key_jstring) {
-        Ok(s) => s,
-        Err(_) => return def,
+fn is_fake_handle(handle: jni::sys::jlong) -> bool {
+    handle & FAKE_HANDLE_TAG_MASK == FAKE_HANDLE_TAG
+}
+
+/// 伪装句柄的低位即为该属性在快照中的位置
+fn fake_entry_for_handle(handle: jni::sys::jlong) -> Option<&'static PropEntry> {
+    let index = (handle & !FAKE_HANDLE_TAG_MASK) as usize;
+    PROP_SNAPSHOT.get()?.entry(index)
+}
+
+/// 为 Hook 提供的 SystemProperties.native_find 替身实现，伪装的属性返回伪装句柄。
+pub unsafe extern "C" fn native_find_hook(
+    env: *mut jni::sys::JNIEnv,
+    class: jni::sys::jclass,
+    key: jni::sys::jstring,
+) -> jni::sys::jlong {
+    let Some(originals) = originals() else {
+        return 0;
     };
-    let key_string: String = key_str.into();
-
-    let result = {
-        let fake_props = FAKE_PROPS.lock().unwrap();
-        if let Some(props) = fake_props.as_ref() {
-            props.get(&key_string).and_then(|fake_value| {
-                env_wrapper
-                    .new_string(fake_value)
-                    .ok()
-                    .map(|s| s.into_raw())
-            })
-        } else {
-            None
+
+    // 隐藏的属性返回 0，与属性不存在时一致；句柄查询方法未全部 Hook 时伪装属性交给原实现
+    match lookup_override(env, key) {
+        Some(PropOverride::Fake { index, .. }) if originals.handle_getters_hooked() => {
+            return FAKE_HANDLE_TAG | index as jni::sys::jlong;
         }
-    };
+        Some(PropOverride::Hidden) => return 0,
+        _ => {}
+    }
 
-    if let Some(fake_result) = result {
-        return fake_result;
+    if let Some(orig_fn) = originals.native_find {
+        return unsafe { orig_fn(env, class, key) };
     }
 
-    let original_native_get = ORIGINAL_NATIVE_GET.lock().unwrap();
-    if let Some(orig_fn) = *original_native_get {
-        return unsafe { orig_fn(env, class, key, def) };
+    0
+}
+
+/// 为 Hook 提供的 SystemProperties.native_get(long) 替身实现。
+pub unsafe extern "C" fn handle_get_hook(
+    env: *mut jni::sys::JNIEnv,
+    class: jni::sys::jclass,
+    handle: jni::sys::jlong,
+) -> jni::sys::jstring {
+    if is_fake_handle(handle) {
+        return match fake_entry_for_handle(handle) {
+            Some(entry) => fake_java_string(env, entry),
+            None => std::ptr::null_mut(),
+        };
+    }
+
+    if let Some(orig_fn) = originals().and_then(|originals| originals.handle_get) {
+        return unsafe { orig_fn(env, class, handle) };
+    }
+
+    std::ptr::null_mut()
+}
+
+/// 为 Hook 提供的 SystemProperties.native_get_int(long, int) 替身实现。
+pub unsafe extern "C" fn handle_get_int_hook(
+    handle: jni::sys::jlong,
+    def: jni::sys::jint,
+) -> jni::sys::jint {
+    if is_fake_handle(handle) {
+        return fake_entry_for_handle(handle).map_or(def, |entry| fake_int(entry, def));
+    }
+
+    if let Some(orig_fn) = originals().and_then(|originals| originals.handle_get_int) {
+        return unsafe { orig_fn(handle, def) };
     }
 
     def
 }
+
+/// 为 Hook 提供的 SystemProperties.native_get_long(long, long) 替身实现。
+pub unsafe extern "C" fn handle_get_long_hook(
+    handle: jni::sys::jlong,
+    def: jni::sys::jlong,
+) -> jni::sys::jlong {
+    if is_fake_handle(handle) {
+        return fake_entry_for_handle(handle).map_or(def, |entry| fake_long(entry, def));
+    }
+
+    if let Some(orig_fn) = originals().and_then(|originals| originals.handle_get_long) {
+        return unsafe { orig_fn(handle, def) };
+    }
+
+    def
+}
+
+/// 为 Hook 提供的 SystemProperties.native_get_boolean(long, boolean) 替身实现。
+pub unsafe extern "C" fn handle_get_boolean_hook(
+    handle: jni::sys::jlong,
+    def: jni::sys::jboolean,
+) -> jni::sys::jboolean {
+    if is_fake_handle(handle) {
+        return fake_entry_for_handle(handle).map_or(def, |entry| fake_boolean(entry, def));
+    }
+
+    if let Some(orig_fn) = originals().and_then(|originals| originals.handle_get_boolean) {
+        return unsafe { orig_fn(handle, def) };
+    }
+
+    def
+}
+
+/// 与 android::base::ParseInt 一致：按 strtoll(value, 0) 解析（支持 0x 十六进制与 0 开头的八进制），
+/// 必须整串解析成功，否则返回 None
+fn parse_android_int(value: &str) -> Option<i64> {
+    let value = value.trim_start();
+    let (negative, unsigned) = match value.as_bytes().first()? {
+        b'-' => (true, &value[1..]),
+        b'+' => (false, &value[1..]),
+        _ => (false, value),
+    };
+
+    let (radix, digits) = if let Some(hex) = unsigned
+        .strip_prefix("0x")
+        .or_else(|| unsigned.strip_prefix("0X"))
+    {
+        (16, hex)
+    } else if unsigned.len() > 1 && unsigned.starts_with('0') {
+        (8, &unsigned[1..])
+    } else {
+        (10, unsigned)
+    };
+
+    if !digits.bytes().next()?.is_ascii_hexdigit() {
+        return None;
+    }
+    let magnitude = i128::from_str_radix(digits, radix).ok()?;
+    i64::try_from(if negative { -magnitude } else { magnitude }).ok()
+}
+
+/// 与 android::base::ParseBool 一致，无法识别的值返回 None
+fn parse_android_bool(value: &str) -> Option<bool> {
+    match value {
+        "1" | "y" | "yes" | "on" | "true" => Some(true),
+        "0" | "n" | "no" | "off" | "false" => Some(false),
+        _ => None,
+    }
+}
@@ -0,0 +1,199 @@
+//! resetprop 会话的还原日志：修改属性前先把原始值落盘，还原进程被杀或 companion 崩溃后仍能恢复。
+//!
+//! resetprop 修改的属性只保存在内存中，重启后自然恢复，因此不同 boot_id 的日志直接丢弃。
+
+use crate::ownership::PropertyValue;
+use anyhow::Context;
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const JOURNAL_DIR: &str = "/data/adb/device_faker/journal";
+const BOOT_ID_PATH: &str = "/proc/sys/kernel/random/boot_id";
+
+/// 串行化日志的创建与回放，避免回放到另一个尚未启动还原进程的会话
+pub static JOURNAL_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Serialize, Deserialize, Debug)]
+struct JournalEntry {
+    boot_id: String,
+    /// 被伪装的应用进程
+    pid: u32,
+    /// 负责还原的进程（companion），交给会话管理线程之前为 None
+    watcher_pid: Option<u32>,
+    /// 负责还原进程的启动时间，用于区分被复用的 pid
+    #[serde(default)]
+    watcher_start_time: Option<u64>,
+    /// 属性名 -> 原始值（被隐藏的属性同样以原始值记录），None 表示原本不存在
+    backups: HashMap<String, PropertyValue>,
+    /// 属性名 -> 写入的值，None 为隐藏；只还原仍是该值的属性
+    #[serde(default)]
+    spoofed: HashMap<String, PropertyValue>,
+}
+
+/// 一个尚未完成还原的会话日志
+pub struct Journal {
+    path: PathBuf,
+    entry: JournalEntry,
+}
+
+impl Journal {
+    /// 在修改任何属性之前写入日志
+    pub fn create(
+        pid: u32,
+        backups: HashMap<String, PropertyValue>,
+        spoofed: HashMap<String, PropertyValue>,
+    ) -> anyhow::Result<Self> {
+        // pid 可能被复用，文件名附带创建时间
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_nanos())
+            .unwrap_or_default();
+        let journal = Self {
+            path: Path::new(JOURNAL_DIR).join(format!("{pid}-{created}.json")),
+            entry: JournalEntry {
+                boot_id: read_boot_id()?,
+                pid,
+                watcher_pid: None,
+                watcher_start_time: None,
+                backups,
+                spoofed,
+            },
+        };
+        journal.persist()?;
+        Ok(journal)
+    }
+
+    /// 记录负责还原的进程，回放时据此判断日志是否仍有进程负责
+    pub fn set_watcher(&mut self, watcher_pid: u32) -> anyhow::Result<()> {
+        self.entry.watcher_pid = Some(watcher_pid);
+        self.entry.watcher_start_time = process_start_time(watcher_pid);
+        self.persist()
+    }
+
+    /// 会话重新生效时原始值可能已被他人修改，改为记录新的原始值
+    pub fn set_backups(&mut self, backups: HashMap<String, PropertyValue>) -> anyhow::Result<()> {
+        self.entry.backups = backups;
+        self.persist()
+    }
+
+    /// 还原完成后删除日志
+    pub fn remove(self) {
+        remove_journal(&self.path);
+    }
+
+    /// 先写临时文件并同步到磁盘，再原子替换，避免崩溃时留下半截日志
+    fn persist(&self) -> anyhow::Result<()> {
+        fs::create_dir_all(JOURNAL_DIR)
+            .with_context(|| format!("Failed to create {JOURNAL_DIR}"))?;
+        let bytes = serde_json::to_vec(&self.entry)?;
+        let tmp_path = self.path.with_extension("json.tmp");
+        let mut file = fs::File::create(&tmp_path)
+            .with_context(|| format!("Failed to create {}", tmp_path.display()))?;
+        file.write_all(&bytes)?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, &self.path)
+            .with_context(|| format!("Failed to write {}", self.path.display()))?;
+        Ok(())
+    }
+}
+
+pub fn remove_journal(path: &Path) {
+    if let Err(e) = fs::remove_file(path)
+        && e.kind() != std::io::ErrorKind::NotFound
+    {
+        warn!("Failed to remove journal {}: {e}", path.display());
+    }
+}
+
+/// 回放无人负责的日志：本次启动中写入、且还原进程已不存在的会话会被立即还原
+///
+/// restore 依次收到属性名、写入的值（旧版本日志为 None）与原始值。
+/// 调用方需持有 [`JOURNAL_LOCK`]。某个属性还原失败时保留日志，留待下次回放。
+pub fn replay_unfinished(
+    restore: impl Fn(&str, Option<&PropertyValue>, &PropertyValue) -> anyhow::Result<()>,
+) {
+    let Ok(dir) = fs::read_dir(JOURNAL_DIR) else {
+        return;
+    };
+    let boot_id = match read_boot_id() {
+        Ok(boot_id) => boot_id,
+        Err(e) => {
+            error!("Failed to replay resetprop journals: {e:#}");
+            return;
+        }
+    };
+
+    for path in dir.flatten().map(|entry| entry.path()) {
+        if path.extension().is_none_or(|extension| extension != "json") {
+            // 写入中途崩溃留下的临时文件
+            remove_journal(&path);
+            continue;
+        }
+
+        let entry = match fs::read(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|bytes| Ok(serde_json::from_slice::<JournalEntry>(&bytes)?))
+        {
+            Ok(entry) => entry,
+            Err(e) => {
+                error!("Discarding unreadable journal {}: {e:#}", path.display());
+                remove_journal(&path);
+                continue;
+            }
+        };
+
+        // 重启后 resetprop 的修改已经失效
+        if entry.boot_id != boot_id {
+            remove_journal(&path);
+            continue;
+        }
+        if entry
+            .watcher_pid
+            .is_some_and(|pid| is_watcher_alive(pid, entry.watcher_start_time))
+        {
+            continue;
+        }
+
+        let mut restored = true;
+        for (key, value) in &entry.backups {
+            if let Err(e) = restore(key, entry.spoofed.get(key), value) {
+                error!(
+                    "Failed to replay property {key} for pid {}: {e:#}",
+                    entry.pid
+                );
+                restored = false;
+            }
+        }
+        if restored {
+            remove_journal(&path);
+        }
+    }
+}
+
+fn read_boot_id() -> anyhow::Result<String> {
+    let boot_id = fs::read_to_string(BOOT_ID_PATH)
+        .with_context(|| format!("Failed to read {BOOT_ID_PATH}"))?;
+    Ok(boot_id.trim().to_string())
+}
+
+/// pid 存在且启动时间一致时才认为负责还原的进程仍在；旧版本日志没有记录启动时间
+fn is_watcher_alive(pid: u32, start_time: Option<u64>) -> bool {
+    match start_time {
+        Some(start_time) => process_start_time(pid) == Some(start_time),
+        None => Path::new(&format!("/proc/{pid}")).exists(),
+    }
+}
+
+/// 读取 /proc/<pid>/stat 的第 22 个字段，即进程启动时间（开机后的时钟滴答数）
+fn process_start_time(pid: u32) -> Option<u64> {
+    let stat = fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    // 进程名可能包含空格与括号，从最后一个 ')' 之后开始按字段切分
+    let (_, fields) = stat.rsplit_once(')')?;
+    fields.split_whitespace().nth(19)?.parse().ok()
+}
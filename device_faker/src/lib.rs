@@ -1,26 +1,42 @@
 mod companion;
 mod config;
 mod hooks;
+mod journal;
+mod matcher;
+mod native_hooks;
+mod ownership;
+#[cfg(target_pointer_width = "64")]
+mod plt;
 mod state;
+mod supervisor;
+
+// plt 模块只解析 64 位 ELF
+#[cfg(not(target_pointer_width = "64"))]
+compile_error!("device_faker only supports 64-bit targets");
 
 use anyhow::Context;
 use companion::{
-    handle_companion_request, restore_previous_resetprop_if_needed,
+    CompanionError, HookStatus, handle_companion_request, report_hook_status,
     spoof_system_props_via_companion,
 };
-use config::{Config, MergedAppConfig};
+use config::{Config, MergedAppConfig, TargetProcess};
 use hooks::{hook_build_fields, hook_system_properties};
 use jni::JNIEnv;
-use log::{LevelFilter, error, info};
-use state::{FAKE_PROPS, IS_FULL_MODE};
+use log::{LevelFilter, error, info, warn};
+use native_hooks::install_native_property_hooks;
+use state::{IS_FULL_MODE, PROP_SNAPSHOT, PropSnapshot};
+use std::ffi::CString;
 use std::fs;
 use std::path::Path;
+use std::sync::atomic::Ordering;
 use zygisk_api::ZygiskModule;
 use zygisk_api::api::v4::ZygiskOption;
 use zygisk_api::api::{V4, ZygiskApi};
 use zygisk_api::raw::ZygiskRaw;
 
 const CONFIG_PATH: &str = "/data/adb/device_faker/config/config.toml";
+/// 每个 Android 用户占用的 uid 区间（AID_USER_OFFSET）
+const PER_USER_RANGE: i32 = 100_000;
 
 #[derive(Default)]
 struct MyModule;
@@ -53,8 +69,7 @@ impl ZygiskModule for MyModule {
         _env: JNIEnv,
         _args: &<V4 as ZygiskRaw>::AppSpecializeArgs,
     ) {
-        let is_full_mode = *IS_FULL_MODE.lock().unwrap();
-        if !is_full_mode {
+        if !IS_FULL_MODE.load(Ordering::Acquire) {
             api.set_option(ZygiskOption::DlCloseModuleLibrary);
         }
     }
@@ -76,8 +91,8 @@ impl MyModule {
         env: &mut JNIEnv,
         args: &mut <V4 as ZygiskRaw>::AppSpecializeArgs,
     ) -> anyhow::Result<()> {
-        let package_name = Self::extract_package_name(env, args)?;
-        restore_previous_resetprop_if_needed(api, &package_name)?;
+        let target = Self::extract_target_process(env, args)?;
+        let package_name = target.package.as_str();
 
         let config = match load_config() {
             Ok(Some(cfg)) => cfg,
@@ -102,9 +117,12 @@ impl MyModule {
             );
         }
 
-        let Some(merged) = config.get_merged_config(&package_name) else {
+        let Some(merged) = config.get_merged_config(&target) else {
             if config.debug {
-                info!("App {package_name} not in config, unloading module");
+                info!(
+                    "Process {} (user {}) of {package_name} not in config, unloading module",
+                    target.process, target.user_id
+                );
             }
             api.set_option(ZygiskOption::DlCloseModuleLibrary);
             return Ok(());
@@ -121,48 +139,93 @@ impl MyModule {
             info!("Using mode: {} for app: {package_name}", merged.mode);
         }
 
-        hook_build_fields(env, &merged)?;
+        let build_report = hook_build_fields(env, &merged)?;
+        for failure in &build_report.failed {
+            error!("Failed to hook {}: {}", failure.field, failure.error);
+        }
         if config.debug {
-            info!("Build fields hooked successfully");
+            info!(
+                "Build fields applied: [{}], skipped (not present): [{}], failed: {}",
+                build_report.applied.join(", "),
+                build_report.skipped.join(", "),
+                build_report.failed.len()
+            );
         }
+        let status = HookStatus::new(package_name, &target.process, &merged.mode, build_report);
 
         match SpoofMode::from_mode_str(&merged.mode) {
-            SpoofMode::Lite => Self::apply_lite_mode(api, config.debug),
-            SpoofMode::Full => Self::apply_full_mode(api, env, &merged, config.debug),
+            SpoofMode::Lite => {
+                Self::report_status(api, status, config.debug);
+                Self::apply_lite_mode(api, config.debug)
+            }
+            SpoofMode::Full => {
+                Self::report_status(api, status, config.debug);
+                Self::apply_full_mode(api, env, &merged, config.debug)
+            }
+            // 状态随 resetprop 请求一起发送
             SpoofMode::Resetprop => {
-                Self::apply_resetprop_mode(api, &package_name, &merged, config.debug)
+                Self::apply_resetprop_mode(api, env, &target, &merged, status, config.debug)
             }
         }
     }
 
-    fn extract_package_name(
-        env: &mut JNIEnv,
-        args: &mut <V4 as ZygiskRaw>::AppSpecializeArgs,
-    ) -> anyhow::Result<String> {
-        if let Ok(app_data_dir) = env.get_string(args.app_data_dir) {
-            let app_data: String = app_data_dir.into();
-            if let Some(package) = app_data.rsplit('/').next()
-                && !package.is_empty()
-            {
-                return Ok(package.to_string());
-            }
+    fn report_status(api: &mut ZygiskApi<V4>, status: HookStatus, debug: bool) {
+        if let Err(err) = report_hook_status(api, status)
+            && debug
+        {
+            warn!("Failed to report hook status: {err:#}");
         }
+    }
 
-        let mut nice_name: String = env
-            .get_string(args.nice_name)
-            .context("Failed to get package name")?
-            .into();
+    /// 提取包名、完整进程名（如 `com.tencent.mm:push`）与 Android 用户 ID
+    fn extract_target_process(
+        env: &mut JNIEnv,
+        args: &mut <V4 as ZygiskRaw>::AppSpecializeArgs,
+    ) -> anyhow::Result<TargetProcess> {
+        let nice_name: Option<String> = env.get_string(args.nice_name).ok().map(Into::into);
+
+        let app_data_dir: Option<String> = env.get_string(args.app_data_dir).ok().map(Into::into);
+        let package_from_data_dir = app_data_dir
+            .as_deref()
+            .and_then(|app_data| app_data.rsplit('/').next())
+            .filter(|package| !package.is_empty())
+            .map(str::to_string);
+
+        let package = match (package_from_data_dir, &nice_name) {
+            (Some(package), _) => package,
+            (None, Some(nice_name)) => nice_name.split(':').next().unwrap_or(nice_name).to_string(),
+            (None, None) => anyhow::bail!("Failed to get package name"),
+        };
+        let process = nice_name.unwrap_or_else(|| package.clone());
+        let user_id = Self::extract_user_id(*args.uid, app_data_dir.as_deref());
+
+        Ok(TargetProcess {
+            package,
+            process,
+            uid: u32::try_from(*args.uid).unwrap_or_default(),
+            user_id,
+        })
+    }
 
-        if let Some(idx) = nice_name.find(':') {
-            nice_name.truncate(idx);
+    /// 优先由 uid 计算用户 ID，uid 无效时从 `/data/user/<id>/<pkg>` 形式的数据目录解析
+    fn extract_user_id(uid: i32, app_data_dir: Option<&str>) -> u32 {
+        if uid >= 0 {
+            return (uid / PER_USER_RANGE) as u32;
         }
 
-        Ok(nice_name)
+        app_data_dir
+            .and_then(|dir| {
+                let mut segments = dir.rsplit('/').skip(1);
+                let user = segments.next()?;
+                matches!(segments.next(), Some("user" | "user_de"))
+                    .then(|| user.parse().ok())
+                    .flatten()
+            })
+            .unwrap_or(0)
     }
 
     fn apply_lite_mode(api: &mut ZygiskApi<V4>, debug: bool) -> anyhow::Result<()> {
-        *FAKE_PROPS.lock().unwrap() = None;
-        *IS_FULL_MODE.lock().unwrap() = false;
+        IS_FULL_MODE.store(false, Ordering::Release);
         if debug {
             info!("Lite mode: only Build fields hooked, unloading module");
         }
@@ -172,7 +235,7 @@ impl MyModule {
 
     fn apply_full_mode(
         api: &mut ZygiskApi<V4>,
-        env: &JNIEnv,
+        env: &mut JNIEnv,
         merged: &MergedAppConfig,
         debug: bool,
     ) -> anyhow::Result<()> {
@@ -180,17 +243,53 @@ impl MyModule {
             info!("Full mode: hooking SystemProperties");
         }
 
-        let prop_map = Config::build_merged_property_map(merged);
+        let prop_map = Config::build_merged_property_map(merged, system_property_exists);
         if debug {
             info!("Property map created with {} entries", prop_map.len());
         }
 
-        *FAKE_PROPS.lock().unwrap() = Some(prop_map);
-        *IS_FULL_MODE.lock().unwrap() = true;
-        hook_system_properties(api, env)?;
+        let snapshot = PropSnapshot::new(env, prop_map, merged.hide_props.clone());
+        if PROP_SNAPSHOT.set(snapshot).is_err() {
+            anyhow::bail!("Property snapshot already initialized");
+        }
+
+        let report = match hook_system_properties(api, env) {
+            Ok(report) => report,
+            Err(err) => {
+                // 没有任何方法被替换，可以安全卸载模块，已写入的 Build 字段仍然生效
+                Self::apply_lite_mode(api, debug)?;
+                return Err(err.context("Full mode unavailable, fell back to lite mode"));
+            }
+        };
+        IS_FULL_MODE.store(true, Ordering::Release);
 
+        if !report.failed.is_empty() {
+            error!(
+                "SystemProperties partially hooked, failed: [{}]",
+                report.failed.join(", ")
+            );
+        }
         if debug {
-            info!("SystemProperties hooked successfully, module will stay loaded");
+            info!(
+                "SystemProperties hooked: [{}], module will stay loaded",
+                report.hooked.join(", ")
+            );
+            if !report.missing.is_empty() {
+                info!(
+                    "SystemProperties natives not present on this system: [{}]",
+                    report.missing.join(", ")
+                );
+            }
+        }
+
+        if merged.native_props {
+            match install_native_property_hooks(api) {
+                Ok(libraries) if debug => {
+                    info!("Native property hooks registered for {libraries} libraries")
+                }
+                Ok(_) => {}
+                Err(err) => error!("Failed to install native property hooks: {err:#}"),
+            }
         }
 
         Ok(())
@@ -198,23 +297,56 @@ impl MyModule {
 
     fn apply_resetprop_mode(
         api: &mut ZygiskApi<V4>,
-        package_name: &str,
+        env: &mut JNIEnv,
+        target: &TargetProcess,
         merged: &MergedAppConfig,
+        status: HookStatus,
         debug: bool,
     ) -> anyhow::Result<()> {
         if debug {
             info!("Resetprop mode: using companion process");
         }
 
-        let prop_map = Config::build_merged_property_map(merged);
-        spoof_system_props_via_companion(api, &prop_map, package_name)?;
+        let prop_map = Config::build_merged_property_map(merged, system_property_exists);
+        let hide_props = merged.profile.hide_patterns();
+        if let Err(err) = spoof_system_props_via_companion(
+            api,
+            target,
+            &prop_map,
+            &hide_props,
+            merged.session_policy,
+            status,
+        ) {
+            // 这些错误发生在修改任何属性之前，改为在进程内 Hook 属性查询
+            match err {
+                CompanionError::UnsupportedVersion { .. }
+                | CompanionError::MissingCapability { .. } => {
+                    error!("Outdated companion: {err}; falling back to full mode")
+                }
+                CompanionError::ResetpropNotFound => {
+                    error!("resetprop is unavailable on this device; falling back to full mode")
+                }
+                CompanionError::InvalidRequest { .. }
+                | CompanionError::BackupFailed { .. }
+                | CompanionError::JournalFailed { .. }
+                | CompanionError::SupervisorFailed { .. } => {
+                    error!("Resetprop session failed: {err}; falling back to full mode")
+                }
+                // 通信失败或写入失败时 companion 可能已经写入并登记了属性，
+                // 再 Hook 属性查询会与 resetprop 的伪装叠加，只保留 Build 字段
+                _ => {
+                    error!("Resetprop session failed: {err}; falling back to lite mode");
+                    return Self::apply_lite_mode(api, debug);
+                }
+            }
+            return Self::apply_full_mode(api, env, merged, debug);
+        }
 
         if debug {
             info!("Resetprop spoofing completed");
         }
 
-        *FAKE_PROPS.lock().unwrap() = None;
-        *IS_FULL_MODE.lock().unwrap() = false;
+        IS_FULL_MODE.store(false, Ordering::Release);
         api.set_option(ZygiskOption::DlCloseModuleLibrary);
         Ok(())
     }
@@ -252,6 +384,14 @@ fn load_config() -> anyhow::Result<Option<Config>> {
     Ok(Some(config))
 }
 
+/// 查询设备上是否存在该属性；在安装 native Hook 之前调用，读到的是真实属性区
+fn system_property_exists(key: &str) -> bool {
+    let Ok(key) = CString::new(key) else {
+        return false;
+    };
+    !unsafe { libc::__system_property_find(key.as_ptr()) }.is_null()
+}
+
 fn configure_log_level(debug_enabled: bool) {
     let level = if debug_enabled {
         LevelFilter::Info
@@ -0,0 +1,146 @@
+use anyhow::{Context, Result};
+use regex_lite::Regex;
+
+/// 正则模式的前缀，例如 `re:com\.tencent\.tmgp\.(sgame|pubgmhd)`
+const REGEX_PREFIX: &str = "re:";
+/// 排除模式的前缀，例如 `!com.tencent.tmgp.sgame`
+const EXCLUDE_PREFIX: char = '!';
+
+/// 模式匹配的具体程度，用于在多个命中的规则之间选出最精确的一条
+///
+/// 排序规则：精确匹配 > 通配/正则匹配；同类之间模式越长越优先
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Specificity {
+    exact: bool,
+    len: usize,
+}
+
+#[derive(Debug, Clone)]
+enum Pattern {
+    /// 普通字符串，完全相等才匹配
+    Exact(String),
+    /// 含 `*`（任意长度）或 `?`（单个字符）的通配模式
+    Glob(String),
+    /// `re:` 前缀的正则表达式，需匹配整个字符串
+    Regex { source: String, regex: Regex },
+}
+
+impl Pattern {
+    fn parse(raw: &str) -> Result<Self> {
+        if let Some(source) = raw.strip_prefix(REGEX_PREFIX) {
+            let regex = Regex::new(&format!("^(?:{source})$"))
+                .with_context(|| format!("Invalid regex pattern '{raw}'"))?;
+            return Ok(Self::Regex {
+                source: source.to_string(),
+                regex,
+            });
+        }
+
+        if raw.contains(['*', '?']) {
+            Ok(Self::Glob(raw.to_string()))
+        } else {
+            Ok(Self::Exact(raw.to_string()))
+        }
+    }
+
+    fn matches(&self, value: &str) -> bool {
+        match self {
+            Self::Exact(expected) => expected == value,
+            Self::Glob(glob) => glob_match(glob.as_bytes(), value.as_bytes()),
+            Self::Regex { regex, .. } => regex.is_match(value),
+        }
+    }
+
+    fn specificity(&self) -> Specificity {
+        match self {
+            Self::Exact(expected) => Specificity {
+                exact: true,
+                len: expected.len(),
+            },
+            Self::Glob(glob) => Specificity {
+                exact: false,
+                len: glob.len(),
+            },
+            Self::Regex { source, .. } => Specificity {
+                exact: false,
+                len: source.len(),
+            },
+        }
+    }
+}
+
+/// 一组包含模式与 `!` 排除模式，命中任一排除模式时整组不匹配
+#[derive(Debug, Clone, Default)]
+pub struct PatternSet {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl PatternSet {
+    pub fn new<S: AsRef<str>>(patterns: &[S]) -> Result<Self> {
+        let mut set = Self::default();
+        for raw in patterns {
+            let raw = raw.as_ref();
+            match raw.strip_prefix(EXCLUDE_PREFIX) {
+                Some(excluded) => set.exclude.push(Pattern::parse(excluded)?),
+                None => set.include.push(Pattern::parse(raw)?),
+            }
+        }
+        Ok(set)
+    }
+
+    /// 是否设置了包含模式（仅有排除模式时视为不限定范围）
+    pub fn has_include(&self) -> bool {
+        !self.include.is_empty()
+    }
+
+    /// 用作过滤条件：未被排除，且未设置包含模式或命中任一包含模式
+    pub fn allows(&self, value: &str) -> bool {
+        if self.exclude.iter().any(|pattern| pattern.matches(value)) {
+            return false;
+        }
+        self.include.is_empty() || self.include.iter().any(|pattern| pattern.matches(value))
+    }
+
+    /// 返回命中的包含模式中最具体的一条，未命中或被排除时返回 None
+    pub fn best_match(&self, value: &str) -> Option<Specificity> {
+        if self.exclude.iter().any(|pattern| pattern.matches(value)) {
+            return None;
+        }
+
+        self.include
+            .iter()
+            .filter(|pattern| pattern.matches(value))
+            .map(Pattern::specificity)
+            .max()
+    }
+}
+
+/// 通配匹配：`*` 匹配任意长度（含空），`?` 匹配单个字节
+pub fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == b'?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    backtrack = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == b'*')
+}
@@ -0,0 +1,310 @@
+//! native 层系统属性 Hook：让 NDK 代码通过 `__system_property_*` 读到与 Java 层一致的伪装值。
+
+use std::ffi::{CStr, c_char, c_int, c_void};
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+use crate::plt::{self, PltHook};
+use crate::state::{PropOverride, lookup_prop_override};
+use zygisk_api::api::{V4, ZygiskApi};
+
+/// bionic 中属性值的最大长度（含结尾 NUL）
+const PROP_VALUE_MAX: usize = 92;
+/// `__system_property_read` 写入属性名时使用的缓冲区长度
+const PROP_NAME_MAX: usize = 32;
+
+type SystemPropertyFind = unsafe extern "C" fn(*const c_char) -> *const c_void;
+type SystemPropertyGet = unsafe extern "C" fn(*const c_char, *mut c_char) -> c_int;
+type SystemPropertyRead = unsafe extern "C" fn(*const c_void, *mut c_char, *mut c_char) -> c_int;
+type ReadCallback = unsafe extern "C" fn(*mut c_void, *const c_char, *const c_char, u32);
+type SystemPropertyReadCallback =
+    unsafe extern "C" fn(*const c_void, Option<ReadCallback>, *mut c_void);
+type LoaderDlopen = unsafe extern "C" fn(*const c_char, c_int, *const c_void) -> *mut c_void;
+type LoaderAndroidDlopenExt =
+    unsafe extern "C" fn(*const c_char, c_int, *const c_void, *const c_void) -> *mut c_void;
+
+static ORIGINAL_PROPERTY_FIND: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+static ORIGINAL_PROPERTY_GET: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+static ORIGINAL_PROPERTY_READ: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+static ORIGINAL_PROPERTY_READ_CALLBACK: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+static ORIGINAL_LOADER_DLOPEN: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+static ORIGINAL_LOADER_DLOPEN_EXT: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+
+/// 模块自身与 libc 的加载基址
+static SKIP_BASES: OnceLock<Vec<usize>> = OnceLock::new();
+
+/// 通过 Zygisk 的 PLT Hook API 改写已加载库中对属性读取函数的引用，
+/// 同时 Hook linker 入口，之后加载的库由 [`plt`] 直接改写 GOT
+///
+/// 返回注册了 Hook 的库数量。
+pub fn install_native_property_hooks(api: &mut ZygiskApi<V4>) -> anyhow::Result<usize> {
+    let libraries = plt::mapped_libraries(skip_bases())?;
+    if libraries.is_empty() {
+        anyhow::bail!("No libraries to hook found in /proc/self/maps");
+    }
+
+    let hooks = hooks();
+    for library in &libraries {
+        for hook in &hooks {
+            // Zygisk 在提交时把原函数写入 original
+            unsafe {
+                api.plt_hook_register(
+                    library.dev,
+                    library.inode,
+                    hook.symbol,
+                    hook.replacement,
+                    Some(&mut *hook.original.as_ptr()),
+                );
+            }
+        }
+    }
+    if !api.plt_hook_commit() {
+        anyhow::bail!("Failed to commit PLT hooks");
+    }
+
+    plt::mark_loaded_libraries();
+    Ok(libraries.len())
+}
+
+/// 不参与改写的库：模块自身与 libc（属性函数的定义方）
+fn skip_bases() -> &'static [usize] {
+    SKIP_BASES.get_or_init(|| {
+        let own = install_native_property_hooks as *const c_void;
+        let libc = unsafe { libc::dlsym(libc::RTLD_DEFAULT, c"__system_property_get".as_ptr()) };
+        [own, libc as *const c_void]
+            .into_iter()
+            .filter(|address| !address.is_null())
+            .filter_map(plt::library_base_of)
+            .collect()
+    })
+}
+
+/// 改写 specialize 之后新加载的库
+fn patch_new_libraries() {
+    plt::patch_loaded_libraries(&hooks(), skip_bases());
+}
+
+fn hooks() -> [PltHook; 6] {
+    [
+        PltHook {
+            symbol: c"__system_property_find",
+            replacement: system_property_find_hook as *mut c_void,
+            original: &ORIGINAL_PROPERTY_FIND,
+        },
+        PltHook {
+            symbol: c"__system_property_get",
+            replacement: system_property_get_hook as *mut c_void,
+            original: &ORIGINAL_PROPERTY_GET,
+        },
+        PltHook {
+            symbol: c"__system_property_read",
+            replacement: system_property_read_hook as *mut c_void,
+            original: &ORIGINAL_PROPERTY_READ,
+        },
+        PltHook {
+            symbol: c"__system_property_read_callback",
+            replacement: system_property_read_callback_hook as *mut c_void,
+            original: &ORIGINAL_PROPERTY_READ_CALLBACK,
+        },
+        // libdl 的 dlopen / android_dlopen_ext 最终都会调用 linker 的这两个入口
+        PltHook {
+            symbol: c"__loader_dlopen",
+            replacement: loader_dlopen_hook as *mut c_void,
+            original: &ORIGINAL_LOADER_DLOPEN,
+        },
+        PltHook {
+            symbol: c"__loader_android_dlopen_ext",
+            replacement: loader_android_dlopen_ext_hook as *mut c_void,
+            original: &ORIGINAL_LOADER_DLOPEN_EXT,
+        },
+    ]
+}
+
+/// 读取保存的原函数，尚未保存时从全局符号表中查找
+fn resolve_original(slot: &AtomicPtr<c_void>, symbol: &CStr) -> *mut c_void {
+    let original = slot.load(Ordering::Acquire);
+    if !original.is_null() {
+        return original;
+    }
+
+    let resolved = unsafe { libc::dlsym(libc::RTLD_DEFAULT, symbol.as_ptr()) };
+    if !resolved.is_null() {
+        slot.store(resolved, Ordering::Release);
+    }
+    resolved
+}
+
+fn prop_override(name: *const c_char) -> Option<PropOverride<'static>> {
+    if name.is_null() {
+        return None;
+    }
+    lookup_prop_override(unsafe { CStr::from_ptr(name) }.to_bytes())
+}
+
+/// 仅返回伪装值，隐藏的属性已在 `__system_property_find` 中处理
+fn fake_prop_value(name: *const c_char) -> Option<&'static CStr> {
+    match prop_override(name)? {
+        PropOverride::Fake { entry, .. } => Some(&entry.value),
+        PropOverride::Hidden => None,
+    }
+}
+
+/// 按 `__system_property_get` 的语义写入属性值：超长时截断，返回写入的长度
+unsafe fn write_prop_value(buffer: *mut c_char, bytes: &[u8]) -> c_int {
+    let len = bytes.len().min(PROP_VALUE_MAX - 1);
+    unsafe {
+        std::ptr::copy_nonoverlapping(bytes.as_ptr(), buffer as *mut u8, len);
+        buffer.add(len).write(0);
+    }
+    len as c_int
+}
+
+/// 隐藏的属性返回空指针，与属性不存在时一致
+unsafe extern "C" fn system_property_find_hook(name: *const c_char) -> *const c_void {
+    if let Some(PropOverride::Hidden) = prop_override(name) {
+        return std::ptr::null();
+    }
+
+    let original = resolve_original(&ORIGINAL_PROPERTY_FIND, c"__system_property_find");
+    if original.is_null() {
+        return std::ptr::null();
+    }
+    let original: SystemPropertyFind = unsafe { std::mem::transmute(original) };
+    unsafe { original(name) }
+}
+
+unsafe extern "C" fn system_property_get_hook(name: *const c_char, value: *mut c_char) -> c_int {
+    if !value.is_null() {
+        match prop_override(name) {
+            Some(PropOverride::Fake { entry, .. }) => {
+                return unsafe { write_prop_value(value, entry.value.to_bytes()) };
+            }
+            Some(PropOverride::Hidden) => return unsafe { write_prop_value(value, b"") },
+            None => {}
+        }
+    }
+
+    let original = resolve_original(&ORIGINAL_PROPERTY_GET, c"__system_property_get");
+    if original.is_null() {
+        return 0;
+    }
+    let original: SystemPropertyGet = unsafe { std::mem::transmute(original) };
+    unsafe { original(name, value) }
+}
+
+unsafe extern "C" fn system_property_read_hook(
+    info: *const c_void,
+    name: *mut c_char,
+    value: *mut c_char,
+) -> c_int {
+    let original = resolve_original(&ORIGINAL_PROPERTY_READ, c"__system_property_read");
+    if original.is_null() {
+        return 0;
+    }
+    let original: SystemPropertyRead = unsafe { std::mem::transmute(original) };
+
+    // 调用方可以不关心属性名，此时仍需读出属性名用于查表
+    let mut name_buffer = [0 as c_char; PROP_NAME_MAX];
+    let name = if name.is_null() {
+        name_buffer.as_mut_ptr()
+    } else {
+        name
+    };
+
+    let len = unsafe { original(info, name, value) };
+    if !value.is_null()
+        && let Some(fake) = fake_prop_value(name)
+    {
+        return unsafe { write_prop_value(value, fake.to_bytes()) };
+    }
+    len
+}
+
+/// 包装调用方的回调，在回调前把真实值替换为伪装值
+struct ReadCallbackTrampoline {
+    callback: ReadCallback,
+    cookie: *mut c_void,
+}
+
+unsafe extern "C" fn system_property_read_callback_hook(
+    info: *const c_void,
+    callback: Option<ReadCallback>,
+    cookie: *mut c_void,
+) {
+    let original = resolve_original(
+        &ORIGINAL_PROPERTY_READ_CALLBACK,
+        c"__system_property_read_callback",
+    );
+    if original.is_null() {
+        return;
+    }
+    let original: SystemPropertyReadCallback = unsafe { std::mem::transmute(original) };
+
+    let Some(callback) = callback else {
+        return unsafe { original(info, None, cookie) };
+    };
+
+    // read_callback 同步调用回调，跳板放在栈上即可
+    let mut trampoline = ReadCallbackTrampoline { callback, cookie };
+    unsafe {
+        original(
+            info,
+            Some(read_callback_trampoline),
+            &mut trampoline as *mut ReadCallbackTrampoline as *mut c_void,
+        )
+    };
+}
+
+unsafe extern "C" fn read_callback_trampoline(
+    cookie: *mut c_void,
+    name: *const c_char,
+    value: *const c_char,
+    serial: u32,
+) {
+    let trampoline = unsafe { &*(cookie as *const ReadCallbackTrampoline) };
+
+    if let Some(fake) = fake_prop_value(name) {
+        return unsafe { (trampoline.callback)(trampoline.cookie, name, fake.as_ptr(), serial) };
+    }
+    unsafe { (trampoline.callback)(trampoline.cookie, name, value, serial) }
+}
+
+/// caller_addr 原样透传，linker 依赖它确定调用方所在的命名空间
+///
+/// 新库的构造函数与 `JNI_OnLoad` 在原函数返回前已经执行，其中的属性读取不会被伪装。
+unsafe extern "C" fn loader_dlopen_hook(
+    filename: *const c_char,
+    flags: c_int,
+    caller_addr: *const c_void,
+) -> *mut c_void {
+    let original = resolve_original(&ORIGINAL_LOADER_DLOPEN, c"__loader_dlopen");
+    if original.is_null() {
+        return std::ptr::null_mut();
+    }
+    let original: LoaderDlopen = unsafe { std::mem::transmute(original) };
+
+    let handle = unsafe { original(filename, flags, caller_addr) };
+    if !handle.is_null() {
+        patch_new_libraries();
+    }
+    handle
+}
+
+unsafe extern "C" fn loader_android_dlopen_ext_hook(
+    filename: *const c_char,
+    flags: c_int,
+    extinfo: *const c_void,
+    caller_addr: *const c_void,
+) -> *mut c_void {
+    let original = resolve_original(&ORIGINAL_LOADER_DLOPEN_EXT, c"__loader_android_dlopen_ext");
+    if original.is_null() {
+        return std::ptr::null_mut();
+    }
+    let original: LoaderAndroidDlopenExt = unsafe { std::mem::transmute(original) };
+
+    let handle = unsafe { original(filename, flags, extinfo, caller_addr) };
+    if !handle.is_null() {
+        patch_new_libraries();
+    }
+    handle
+}
@@ -0,0 +1,97 @@
+//! 多个 resetprop 会话修改同一全局属性时的仲裁。
+//!
+//! 每个被接管的属性记录真实原始值，以及按生效先后排列的覆盖栈，栈顶即当前写入的值。
+//! 会话生效（首次应用或回到前台）时移到栈顶，因此最近生效的会话胜出；
+//! 只有栈为空、没有会话仍需要该属性时才写回真实原始值。
+
+use std::collections::HashMap;
+
+pub type SessionId = u64;
+
+/// 会话对某个属性的要求：Some 为伪装值，None 为隐藏（删除）
+pub type PropertyValue = Option<String>;
+
+struct OwnedProperty {
+    /// 第一个会话接管前的真实值，None 表示属性原本不存在，还原时删除
+    original: PropertyValue,
+    /// 按生效先后排列，栈顶为最近生效的会话
+    stack: Vec<(SessionId, PropertyValue)>,
+}
+
+/// 会话失效后需要改写的属性
+pub struct PropertyWrite {
+    pub key: String,
+    /// 失效会话写入的值，只有属性仍是该值时才改写
+    pub expected: PropertyValue,
+    pub value: PropertyValue,
+}
+
+#[derive(Default)]
+pub struct PropertyOwners {
+    properties: HashMap<String, OwnedProperty>,
+}
+
+impl PropertyOwners {
+    /// 已被其他会话接管的属性返回真实原始值，此时系统中读到的是伪装值
+    pub fn original(&self, key: &str) -> Option<&PropertyValue> {
+        self.properties.get(key).map(|property| &property.original)
+    }
+
+    /// 所有被接管属性的真实原始值，包括已被隐藏、getprop 列不出来的属性
+    pub fn originals(&self) -> impl Iterator<Item = (&str, Option<&str>)> {
+        self.properties
+            .iter()
+            .map(|(key, property)| (key.as_str(), property.original.as_deref()))
+    }
+
+    /// 会话生效：把它的覆盖移到栈顶，返回需要写入的属性
+    ///
+    /// originals 只用于此前无人接管的属性。
+    pub fn push(
+        &mut self,
+        session: SessionId,
+        values: Vec<(String, PropertyValue)>,
+        originals: &HashMap<String, PropertyValue>,
+    ) -> Vec<(String, PropertyValue)> {
+        for (key, value) in &values {
+            let property = self
+                .properties
+                .entry(key.clone())
+                .or_insert_with(|| OwnedProperty {
+                    original: originals.get(key).cloned().flatten(),
+                    stack: Vec::new(),
+                });
+            property.stack.retain(|(owner, _)| *owner != session);
+            property.stack.push((session, value.clone()));
+        }
+        values
+    }
+
+    /// 会话失效：从所有覆盖栈中移除，返回需要写入的属性
+    ///
+    /// 只有该会话位于栈顶的属性需要改写：栈中仍有其他会话时写入新的栈顶值，否则写回真实原始值。
+    pub fn release(&mut self, session: SessionId) -> Vec<PropertyWrite> {
+        let mut writes = Vec::new();
+        self.properties.retain(|key, property| {
+            let expected = match property.stack.last() {
+                Some((owner, value)) if *owner == session => Some(value.clone()),
+                _ => None,
+            };
+            property.stack.retain(|(owner, _)| *owner != session);
+            let Some(expected) = expected else {
+                return !property.stack.is_empty();
+            };
+            let value = match property.stack.last() {
+                Some((_, value)) => value.clone(),
+                None => property.original.clone(),
+            };
+            writes.push(PropertyWrite {
+                key: key.clone(),
+                expected,
+                value,
+            });
+            !property.stack.is_empty()
+        });
+        writes
+    }
+}
@@ -0,0 +1,387 @@
+//! 基于 GOT 改写的 PLT Hook，用于拦截 specialize 之后才加载的动态库对 libc 函数的调用。
+//!
+//! specialize 阶段已加载的库交给 Zygisk 的 PLT Hook API（按 [`mapped_libraries`] 列出的 dev / inode 注册），
+//! 但游戏引擎的 so 通常在之后才加载，此时 API 已不可用，
+//! 因此对新加载的库直接解析其重定位表，改写目标符号对应的 GOT 槽位。
+//! 只解析 64 位 ELF，lib.rs 中对其他目标会直接编译失败。
+
+use std::collections::HashSet;
+use std::ffi::{CStr, c_char, c_int, c_void};
+use std::fs;
+use std::ops::Range;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+const DT_NULL: i64 = 0;
+const DT_PLTRELSZ: i64 = 2;
+const DT_STRTAB: i64 = 5;
+const DT_SYMTAB: i64 = 6;
+const DT_RELA: i64 = 7;
+const DT_RELASZ: i64 = 8;
+const DT_JMPREL: i64 = 23;
+
+/// 程序头 p_flags 中的段权限
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+
+#[repr(C)]
+struct Elf64Dyn {
+    d_tag: i64,
+    d_val: u64,
+}
+
+#[repr(C)]
+struct Elf64Rela {
+    r_offset: u64,
+    r_info: u64,
+    r_addend: i64,
+}
+
+/// 仅使用 st_name，其余字段用于保持与 ELF 一致的内存布局
+#[repr(C)]
+#[allow(dead_code)]
+struct Elf64Sym {
+    st_name: u32,
+    st_info: u8,
+    st_other: u8,
+    st_shndx: u16,
+    st_value: u64,
+    st_size: u64,
+}
+
+/// 单个符号的 Hook 描述
+pub struct PltHook {
+    pub symbol: &'static CStr,
+    pub replacement: *mut c_void,
+    /// 首次改写时保存的原始函数地址
+    pub original: &'static AtomicPtr<c_void>,
+}
+
+/// 已处理过的库（加载基址 + 路径），避免每次 dlopen 后重复扫描
+static PATCHED_LIBRARIES: Mutex<Option<HashSet<(usize, String)>>> = Mutex::new(None);
+
+/// /proc/self/maps 中的一个已加载动态库，Zygisk 按 dev / inode 定位要 Hook 的库
+pub struct MappedLibrary {
+    pub dev: libc::dev_t,
+    pub inode: libc::ino_t,
+}
+
+/// 列出当前映射的所有动态库，跳过加载基址位于 `skip_bases` 中的库
+pub fn mapped_libraries(skip_bases: &[usize]) -> anyhow::Result<Vec<MappedLibrary>> {
+    let maps = fs::read_to_string("/proc/self/maps")?;
+    let mut seen = HashSet::new();
+    let mut libraries = Vec::new();
+
+    // 格式：start-end perms offset major:minor inode path
+    for line in maps.lines() {
+        let mut fields = line.split_whitespace();
+        let (Some(range), Some(_), Some(offset), Some(dev), Some(inode), Some(path)) = (
+            fields.next(),
+            fields.next(),
+            fields.next(),
+            fields.next(),
+            fields.next(),
+            fields.next(),
+        ) else {
+            continue;
+        };
+        if !path.ends_with(".so") || u64::from_str_radix(offset, 16) != Ok(0) {
+            continue;
+        }
+
+        let Some(start) = range
+            .split_once('-')
+            .and_then(|(start, _)| usize::from_str_radix(start, 16).ok())
+        else {
+            continue;
+        };
+        let Some((major, minor)) = dev.split_once(':').and_then(|(major, minor)| {
+            Some((
+                u32::from_str_radix(major, 16).ok()?,
+                u32::from_str_radix(minor, 16).ok()?,
+            ))
+        }) else {
+            continue;
+        };
+        let Ok(inode) = inode.parse::<libc::ino_t>() else {
+            continue;
+        };
+        if inode == 0 || skip_bases.contains(&start) {
+            continue;
+        }
+
+        let dev = libc::makedev(major, minor);
+        if seen.insert((dev, inode)) {
+            libraries.push(MappedLibrary { dev, inode });
+        }
+    }
+    Ok(libraries)
+}
+
+/// 把当前已加载的库记为已处理，之后的 dlopen 只会改写新加载的库
+pub fn mark_loaded_libraries() {
+    unsafe {
+        libc::dl_iterate_phdr(Some(mark_library_callback), std::ptr::null_mut());
+    }
+}
+
+unsafe extern "C" fn mark_library_callback(
+    info: *mut libc::dl_phdr_info,
+    _size: libc::size_t,
+    _data: *mut c_void,
+) -> c_int {
+    let info = unsafe { &*info };
+    mark_patched(info);
+    0
+}
+
+/// 记录库已处理，已记录过时返回 false
+fn mark_patched(info: &libc::dl_phdr_info) -> bool {
+    let name = if info.dlpi_name.is_null() {
+        String::new()
+    } else {
+        unsafe { CStr::from_ptr(info.dlpi_name) }
+            .to_string_lossy()
+            .into_owned()
+    };
+
+    PATCHED_LIBRARIES
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .get_or_insert_with(HashSet::new)
+        .insert((info.dlpi_addr as usize, name))
+}
+
+/// 库中各地址原本的页面权限
+struct SegmentProtections {
+    relro: Option<Range<usize>>,
+    loads: Vec<(Range<usize>, c_int)>,
+}
+
+impl SegmentProtections {
+    /// RELRO 区域在重定位完成后为只读，其余按所在 PT_LOAD 段的 p_flags
+    fn of(&self, address: usize) -> c_int {
+        if self
+            .relro
+            .as_ref()
+            .is_some_and(|range| range.contains(&address))
+        {
+            return libc::PROT_READ;
+        }
+        self.loads
+            .iter()
+            .find(|(range, _)| range.contains(&address))
+            .map_or(libc::PROT_READ, |(_, protection)| *protection)
+    }
+}
+
+struct PatchContext<'a> {
+    hooks: &'a [PltHook],
+    skip_bases: &'a [usize],
+    patched_slots: usize,
+}
+
+/// 扫描当前进程内所有已加载的库并改写目标符号的 GOT 槽位，返回本次改写的槽位数量
+///
+/// `skip_bases` 中的库（例如模块自身与 libc）不会被改写，以免 Hook 调用到自己。
+pub fn patch_loaded_libraries(hooks: &[PltHook], skip_bases: &[usize]) -> usize {
+    let mut context = PatchContext {
+        hooks,
+        skip_bases,
+        patched_slots: 0,
+    };
+
+    unsafe {
+        libc::dl_iterate_phdr(
+            Some(patch_library_callback),
+            &mut context as *mut PatchContext as *mut c_void,
+        );
+    }
+
+    context.patched_slots
+}
+
+/// 返回某个地址所在库的加载基址
+pub fn library_base_of(address: *const c_void) -> Option<usize> {
+    let mut info: libc::Dl_info = unsafe { std::mem::zeroed() };
+    if unsafe { libc::dladdr(address, &mut info) } == 0 || info.dli_fbase.is_null() {
+        return None;
+    }
+    Some(info.dli_fbase as usize)
+}
+
+unsafe extern "C" fn patch_library_callback(
+    info: *mut libc::dl_phdr_info,
+    _size: libc::size_t,
+    data: *mut c_void,
+) -> c_int {
+    let info = unsafe { &*info };
+    let context = unsafe { &mut *(data as *mut PatchContext) };
+    let bias = info.dlpi_addr as usize;
+
+    if bias == 0
+        || info.dlpi_phdr.is_null()
+        || context.skip_bases.contains(&bias)
+        || !mark_patched(info)
+    {
+        return 0;
+    }
+
+    let phdrs = unsafe { std::slice::from_raw_parts(info.dlpi_phdr, info.dlpi_phnum as usize) };
+    let Some(dynamic) = phdrs.iter().find(|phdr| phdr.p_type == libc::PT_DYNAMIC) else {
+        return 0;
+    };
+    let segment = |phdr: &libc::Elf64_Phdr| {
+        let start = bias + phdr.p_vaddr as usize;
+        start..start + phdr.p_memsz as usize
+    };
+    let protections = SegmentProtections {
+        relro: phdrs
+            .iter()
+            .find(|phdr| phdr.p_type == libc::PT_GNU_RELRO)
+            .map(segment),
+        loads: phdrs
+            .iter()
+            .filter(|phdr| phdr.p_type == libc::PT_LOAD)
+            .map(|phdr| (segment(phdr), protection_of(phdr.p_flags)))
+            .collect(),
+    };
+
+    let dynamic = (bias + dynamic.p_vaddr as usize) as *const Elf64Dyn;
+    context.patched_slots += unsafe { patch_dynamic(bias, dynamic, &protections, context.hooks) };
+    0
+}
+
+/// 解析 .dynamic 段，依次处理 PLT 重定位表（DT_JMPREL）与普通重定位表（DT_RELA）
+unsafe fn patch_dynamic(
+    bias: usize,
+    dynamic: *const Elf64Dyn,
+    protections: &SegmentProtections,
+    hooks: &[PltHook],
+) -> usize {
+    let (mut strtab, mut symtab) = (0usize, 0usize);
+    let (mut jmprel, mut jmprel_size) = (0usize, 0usize);
+    let (mut rela, mut rela_size) = (0usize, 0usize);
+
+    let mut entry = dynamic;
+    loop {
+        let Elf64Dyn { d_tag, d_val } = unsafe { entry.read() };
+        match d_tag {
+            DT_NULL => break,
+            DT_STRTAB => strtab = bias + d_val as usize,
+            DT_SYMTAB => symtab = bias + d_val as usize,
+            DT_JMPREL => jmprel = bias + d_val as usize,
+            DT_PLTRELSZ => jmprel_size = d_val as usize,
+            DT_RELA => rela = bias + d_val as usize,
+            DT_RELASZ => rela_size = d_val as usize,
+            _ => {}
+        }
+        entry = unsafe { entry.add(1) };
+    }
+
+    if strtab == 0 || symtab == 0 {
+        return 0;
+    }
+
+    let mut patched = 0;
+    for (table, size) in [(jmprel, jmprel_size), (rela, rela_size)] {
+        if table == 0 || size == 0 {
+            continue;
+        }
+        let relocations = unsafe {
+            std::slice::from_raw_parts(
+                table as *const Elf64Rela,
+                size / std::mem::size_of::<Elf64Rela>(),
+            )
+        };
+        for relocation in relocations {
+            patched +=
+                unsafe { patch_relocation(bias, relocation, strtab, symtab, protections, hooks) };
+        }
+    }
+    patched
+}
+
+unsafe fn patch_relocation(
+    bias: usize,
+    relocation: &Elf64Rela,
+    strtab: usize,
+    symtab: usize,
+    protections: &SegmentProtections,
+    hooks: &[PltHook],
+) -> usize {
+    let symbol_index = (relocation.r_info >> 32) as usize;
+    if symbol_index == 0 || relocation.r_addend != 0 {
+        return 0;
+    }
+
+    let symbol = unsafe { &*(symtab as *const Elf64Sym).add(symbol_index) };
+    let name = unsafe { CStr::from_ptr((strtab + symbol.st_name as usize) as *const c_char) };
+    let Some(hook) = hooks.iter().find(|hook| hook.symbol == name) else {
+        return 0;
+    };
+
+    let slot = (bias + relocation.r_offset as usize) as *mut *mut c_void;
+    let current = unsafe { slot.read() };
+    if current.is_null() || current == hook.replacement {
+        return 0;
+    }
+
+    // 先保存原函数再改写槽位，保证 Hook 被调用时原函数已可用
+    let _ = hook.original.compare_exchange(
+        std::ptr::null_mut(),
+        current,
+        Ordering::AcqRel,
+        Ordering::Acquire,
+    );
+
+    let protection = protections.of(slot as usize);
+    if unsafe { write_slot(slot, hook.replacement, protection) } {
+        1
+    } else {
+        0
+    }
+}
+
+fn protection_of(flags: u32) -> c_int {
+    let mut protection = libc::PROT_NONE;
+    if flags & PF_R != 0 {
+        protection |= libc::PROT_READ;
+    }
+    if flags & PF_W != 0 {
+        protection |= libc::PROT_WRITE;
+    }
+    if flags & PF_X != 0 {
+        protection |= libc::PROT_EXEC;
+    }
+    protection
+}
+
+/// 改写槽位；页面不可写时临时放开写权限，写完后恢复为原本的权限
+unsafe fn write_slot(slot: *mut *mut c_void, value: *mut c_void, protection: c_int) -> bool {
+    if protection & libc::PROT_WRITE != 0 {
+        unsafe { slot.write(value) };
+        return true;
+    }
+
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+    let page = (slot as usize) & !(page_size - 1);
+
+    if unsafe {
+        libc::mprotect(
+            page as *mut c_void,
+            page_size,
+            libc::PROT_READ | libc::PROT_WRITE,
+        )
+    } != 0
+    {
+        return false;
+    }
+
+    unsafe {
+        slot.write(value);
+        libc::mprotect(page as *mut c_void, page_size, protection);
+    }
+    true
+}
@@ -1,5 +1,10 @@
+use crate::matcher::PatternSet;
+use jni::JNIEnv;
+use jni::objects::GlobalRef;
 use std::collections::HashMap;
-use std::sync::Mutex;
+use std::ffi::CString;
+use std::sync::OnceLock;
+use std::sync::atomic::AtomicBool;
 
 /// 用于恢复真实属性值的 native_get 原始函数签名。
 pub type OriginalNativeGet = unsafe extern "C" fn(
@@ -9,13 +14,145 @@ pub type OriginalNativeGet = unsafe extern "C" fn(
     def: jni::sys::jstring,
 ) -> jni::sys::jstring;
 
-pub static FAKE_PROPS: Mutex<Option<HashMap<String, String>>> = Mutex::new(None);
-pub static IS_FULL_MODE: Mutex<bool> = Mutex::new(false);
-pub static ACTIVE_RESET_SESSION: Mutex<Option<ActiveResetSession>> = Mutex::new(None);
-pub static ORIGINAL_NATIVE_GET: Mutex<Option<OriginalNativeGet>> = Mutex::new(None);
+/// SystemProperties.native_get_int / native_get_long / native_get_boolean 的原始函数签名。
+pub type OriginalNativeGetInt = unsafe extern "C" fn(
+    env: *mut jni::sys::JNIEnv,
+    class: jni::sys::jclass,
+    key: jni::sys::jstring,
+    def: jni::sys::jint,
+) -> jni::sys::jint;
+pub type OriginalNativeGetLong = unsafe extern "C" fn(
+    env: *mut jni::sys::JNIEnv,
+    class: jni::sys::jclass,
+    key: jni::sys::jstring,
+    def: jni::sys::jlong,
+) -> jni::sys::jlong;
+pub type OriginalNativeGetBoolean = unsafe extern "C" fn(
+    env: *mut jni::sys::JNIEnv,
+    class: jni::sys::jclass,
+    key: jni::sys::jstring,
+    def: jni::sys::jboolean,
+) -> jni::sys::jboolean;
+
+/// Android 14+ 基于 Handle 的查询：native_find 返回属性句柄，
+/// native_get(long) 为 @FastNative，其余三个为 @CriticalNative（没有 env 与 class 参数）。
+pub type OriginalNativeFind = unsafe extern "C" fn(
+    env: *mut jni::sys::JNIEnv,
+    class: jni::sys::jclass,
+    key: jni::sys::jstring,
+) -> jni::sys::jlong;
+pub type OriginalHandleGet = unsafe extern "C" fn(
+    env: *mut jni::sys::JNIEnv,
+    class: jni::sys::jclass,
+    handle: jni::sys::jlong,
+) -> jni::sys::jstring;
+pub type OriginalHandleGetInt =
+    unsafe extern "C" fn(handle: jni::sys::jlong, def: jni::sys::jint) -> jni::sys::jint;
+pub type OriginalHandleGetLong =
+    unsafe extern "C" fn(handle: jni::sys::jlong, def: jni::sys::jlong) -> jni::sys::jlong;
+pub type OriginalHandleGetBoolean =
+    unsafe extern "C" fn(handle: jni::sys::jlong, def: jni::sys::jboolean) -> jni::sys::jboolean;
+
+/// Hook 前保存的 SystemProperties 原始 native 函数，未找到的方法为 None
+#[derive(Default)]
+pub struct OriginalNatives {
+    pub native_get: Option<OriginalNativeGet>,
+    pub native_get_int: Option<OriginalNativeGetInt>,
+    pub native_get_long: Option<OriginalNativeGetLong>,
+    pub native_get_boolean: Option<OriginalNativeGetBoolean>,
+    pub native_find: Option<OriginalNativeFind>,
+    pub handle_get: Option<OriginalHandleGet>,
+    pub handle_get_int: Option<OriginalHandleGetInt>,
+    pub handle_get_long: Option<OriginalHandleGetLong>,
+    pub handle_get_boolean: Option<OriginalHandleGetBoolean>,
+}
+
+impl OriginalNatives {
+    /// 四个句柄查询方法都已 Hook 时才能交出伪装句柄，否则原实现会把它当作 prop_info 指针解引用
+    pub fn handle_getters_hooked(&self) -> bool {
+        self.handle_get.is_some()
+            && self.handle_get_int.is_some()
+            && self.handle_get_long.is_some()
+            && self.handle_get_boolean.is_some()
+    }
+}
+
+/// full 模式下的属性快照，在 specialize 阶段设置一次，之后只读
+pub static PROP_SNAPSHOT: OnceLock<PropSnapshot> = OnceLock::new();
+pub static ORIGINAL_NATIVES: OnceLock<OriginalNatives> = OnceLock::new();
+pub static IS_FULL_MODE: AtomicBool = AtomicBool::new(false);
+
+/// 一条伪装属性：属性名、属性值，以及预先创建好的 Java 字符串
+pub struct PropEntry {
+    pub key: CString,
+    pub value: CString,
+    /// 全局引用可以直接作为 native 方法的返回值，避免每次调用都创建字符串
+    pub java_value: Option<GlobalRef>,
+}
+
+/// 不可变的属性快照：按属性名字节序排列的伪装属性，以及隐藏规则
+///
+/// 查询过程不加锁、不会 panic，除 `re:` 正则隐藏规则外也不分配内存，可以在任意线程的 Hook 中调用。
+pub struct PropSnapshot {
+    entries: Vec<PropEntry>,
+    hidden: PatternSet,
+}
+
+/// full 模式下某个属性的处理方式
+pub enum PropOverride<'a> {
+    /// 返回伪装值，index 为该属性在快照中的位置
+    Fake { index: usize, entry: &'a PropEntry },
+    /// 视为属性不存在
+    Hidden,
+}
+
+impl PropSnapshot {
+    /// 构建快照；包含 NUL 字符的属性无法传给 native 调用方，会被忽略
+    pub fn new(env: &mut JNIEnv, props: HashMap<String, String>, hidden: PatternSet) -> Self {
+        let mut entries: Vec<PropEntry> = props
+            .into_iter()
+            .filter_map(|(key, value)| {
+                let java_value = env.new_string(&value).ok().and_then(|local| {
+                    let global = env.new_global_ref(&local).ok();
+                    let _ = env.delete_local_ref(local);
+                    global
+                });
+                Some(PropEntry {
+                    key: CString::new(key).ok()?,
+                    value: CString::new(value).ok()?,
+                    java_value,
+                })
+            })
+            .collect();
+        entries.sort_by(|a, b| a.key.as_bytes().cmp(b.key.as_bytes()));
+
+        Self { entries, hidden }
+    }
+
+    pub fn entry(&self, index: usize) -> Option<&PropEntry> {
+        self.entries.get(index)
+    }
+
+    /// 查询属性是否需要伪装或隐藏；props 中显式设置的值优先于隐藏规则
+    ///
+    /// `re:` 正则隐藏规则是唯一会分配内存的路径，其余规则只做比较。
+    pub fn lookup(&self, key: &[u8]) -> Option<PropOverride<'_>> {
+        if let Ok(index) = self
+            .entries
+            .binary_search_by(|entry| entry.key.as_bytes().cmp(key))
+        {
+            return Some(PropOverride::Fake {
+                index,
+                entry: &self.entries[index],
+            });
+        }
+
+        let key = std::str::from_utf8(key).ok()?;
+        self.hidden.best_match(key).map(|_| PropOverride::Hidden)
+    }
+}
 
-#[derive(Clone)]
-pub struct ActiveResetSession {
-    pub package: String,
-    pub backups: HashMap<String, String>,
+/// 查询当前进程的属性快照，未处于 full 模式时返回 None
+pub fn lookup_prop_override(key: &[u8]) -> Option<PropOverride<'static>> {
+    PROP_SNAPSHOT.get()?.lookup(key)
 }
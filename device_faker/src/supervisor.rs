@@ -0,0 +1,642 @@
+//! companion 内常驻的 resetprop 会话管理线程。
+//!
+//! 会话按包名与 uid 区分，覆盖该 uid 下的所有进程，最后一个进程退出时才结束。
+//! 所有会话由同一个线程管理：通过 pidfd 感知进程退出，通过 logcat events 缓冲区中的
+//! Activity 切换事件感知前台变化，不再为每个会话 fork 一个轮询进程。
+//! 何时还原由会话的 [`SessionPolicy`] 决定，多个会话修改同一属性时由 [`PropertyOwners`] 仲裁。
+//! 内核不支持 pidfd 或 logcat 无法启动时，退化为定时检查。
+
+use crate::companion::{PropBackup, compare_and_write, list_properties, write_property};
+use crate::config::SessionPolicy;
+use crate::journal::Journal;
+use crate::ownership::{PropertyOwners, PropertyValue, PropertyWrite, SessionId};
+use log::{error, warn};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::fs::MetadataExt;
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+
+/// 前台切换事件到达后，应用进程的 cgroup 会稍后才更新，届时再检查一次
+const FOREGROUND_RECHECK_MS: i32 = 1000;
+/// 缺少 pidfd 或事件源时的定时检查间隔
+const FALLBACK_POLL_MS: i32 = 1000;
+
+/// 触发前台检查的 events 标签，不同系统版本的标签前缀为 am_ 或 wm_
+const FOREGROUND_EVENT_TAGS: [&str; 5] = [
+    "am_set_resumed_activity:I",
+    "wm_set_resumed_activity:I",
+    "am_pause_activity:I",
+    "wm_pause_activity:I",
+    "screen_toggled:I",
+];
+
+static SUPERVISOR: OnceLock<Supervisor> = OnceLock::new();
+/// 保证只启动一个管理线程，启动失败时下次调用会重试
+static START_LOCK: Mutex<()> = Mutex::new(());
+
+/// 会话按包名与 uid 区分，同一应用的多个进程共用一个会话
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionKey {
+    pub package: String,
+    pub uid: u32,
+}
+
+/// 会话写入的属性：回到前台时重新写入 props 并删除 hidden，结束时按 backups 还原
+pub struct SpoofedProperties {
+    pub props: HashMap<String, String>,
+    pub hidden: Vec<String>,
+    pub backups: Vec<PropBackup>,
+}
+
+/// 一个已应用伪装、等待还原的 resetprop 会话
+pub struct Session {
+    id: SessionId,
+    key: SessionKey,
+    /// 该 uid 下存活的进程；内核不支持 pidfd_open 时 pidfd 为 None
+    processes: HashMap<u32, Option<OwnedFd>>,
+    policy: SessionPolicy,
+    spoof: SpoofedProperties,
+    resetprop_path: String,
+    /// reboot 策略的会话不会还原，也没有日志
+    journal: Option<Journal>,
+    /// 伪装当前是否生效；foreground 策略下离开前台时为 false
+    applied: bool,
+    /// 撤销时写入失败的属性，管理线程每轮重试
+    unrestored: Vec<PropertyWrite>,
+}
+
+impl Session {
+    /// 同一应用的其他进程加入已有会话
+    pub fn add_process(&mut self, pid: u32) {
+        self.processes.entry(pid).or_insert_with(|| pidfd_open(pid));
+    }
+
+    /// 会话记录的原始值
+    fn backups(&self) -> HashMap<String, PropertyValue> {
+        self.spoof
+            .backups
+            .iter()
+            .map(|entry| (entry.key.clone(), entry.original_value.clone()))
+            .collect()
+    }
+
+    /// 所有进程退出时结束会话；还原全部成功后删除日志，否则返回会话，由管理线程继续重试
+    ///
+    /// reboot 策略的覆盖留在栈中，其他会话结束后仍会写回它的伪装值。
+    fn finish(mut self, owners: &mut PropertyOwners) -> Option<Self> {
+        if self.policy == SessionPolicy::Reboot {
+            return None;
+        }
+        if self.deactivate(owners) {
+            self.remove_journal();
+            return None;
+        }
+        Some(self)
+    }
+
+    fn remove_journal(&mut self) {
+        if let Some(journal) = self.journal.take() {
+            journal.remove();
+        }
+    }
+
+    /// 使伪装生效并成为这些属性的栈顶，遇到第一个写入失败的属性即返回
+    ///
+    /// current 为重新生效前读取的全部属性，会话刚创建、备份刚读取时为 None。
+    fn activate(
+        &mut self,
+        owners: &mut PropertyOwners,
+        current: Option<&HashMap<String, String>>,
+    ) -> Result<(), (String, anyhow::Error)> {
+        self.applied = true;
+        // 尚未还原的属性会被重新写入伪装值，不再重试
+        self.unrestored.clear();
+        let spoofed = self
+            .spoof
+            .props
+            .iter()
+            .map(|(key, value)| (key.clone(), Some(value.clone())))
+            .chain(self.spoof.hidden.iter().map(|key| (key.clone(), None)));
+
+        let mut values = Vec::new();
+        let mut refreshed = HashMap::new();
+        for (key, value) in spoofed {
+            // 无人接管的属性在后台期间可能被他人修改，以当前值作为新的原始值；已是目标值的不再写入
+            if let Some(current) = current
+                && owners.original(&key).is_none()
+            {
+                let original = current.get(&key).cloned();
+                if original == value {
+                    continue;
+                }
+                refreshed.insert(key.clone(), original);
+            }
+            values.push((key, value));
+        }
+        self.refresh_backups(refreshed);
+
+        for (key, value) in owners.push(self.id, values, &self.backups()) {
+            write_property(&self.resetprop_path, &key, value.as_deref())
+                .map_err(|err| (key, err))?;
+        }
+        Ok(())
+    }
+
+    /// 更新重新读取的原始值，并同步到日志
+    fn refresh_backups(&mut self, refreshed: HashMap<String, PropertyValue>) {
+        let mut changed = false;
+        for entry in &mut self.spoof.backups {
+            if let Some(original) = refreshed.get(&entry.key)
+                && entry.original_value != *original
+            {
+                entry.original_value = original.clone();
+                changed = true;
+            }
+        }
+        if !changed {
+            return;
+        }
+        let backups = self.backups();
+        if let Some(journal) = &mut self.journal
+            && let Err(e) = journal.set_backups(backups)
+        {
+            error!("Failed to update journal for {}: {e:#}", self.key.package);
+        }
+    }
+
+    /// 撤销本会话的覆盖，返回是否全部写入成功
+    fn deactivate(&mut self, owners: &mut PropertyOwners) -> bool {
+        self.applied = false;
+        let writes = owners.release(self.id);
+        self.restore(writes)
+    }
+
+    /// 重试上次写入失败的属性，返回是否已全部还原
+    fn retry_restore(&mut self) -> bool {
+        let writes = std::mem::take(&mut self.unrestored);
+        self.restore(writes)
+    }
+
+    /// 写入失败的属性记入 unrestored，返回是否全部写入成功
+    fn restore(&mut self, writes: Vec<PropertyWrite>) -> bool {
+        if writes.is_empty() {
+            return true;
+        }
+        let current = match list_properties() {
+            Ok(current) => current,
+            Err(e) => {
+                error!(
+                    "Failed to read properties before restoring {}: {e:#}",
+                    self.key.package
+                );
+                self.unrestored = writes;
+                return false;
+            }
+        };
+
+        for write in writes {
+            if let Err(e) = compare_and_write(
+                &self.resetprop_path,
+                &current,
+                &write.key,
+                write.expected.as_deref(),
+                write.value.as_deref(),
+            ) {
+                error!(
+                    "Failed to restore property {} for {}: {e}",
+                    write.key, self.key.package
+                );
+                self.unrestored.push(write);
+            }
+        }
+        self.unrestored.is_empty()
+    }
+
+    /// 移除已退出的进程，并加入该 uid 下新出现的进程（如随后启动的 `:push` 进程）
+    fn refresh_processes(&mut self, exited: &[RawFd], running: &HashMap<u32, Vec<u32>>) {
+        let mut removed = Vec::new();
+        self.processes.retain(|&pid, pidfd| {
+            let alive = match pidfd {
+                Some(pidfd) => !exited.contains(&pidfd.as_raw_fd()),
+                None => is_process_alive(pid),
+            };
+            if !alive {
+                removed.push(pid);
+            }
+            alive
+        });
+
+        // 刚退出的进程在被回收前仍会出现在 /proc 中，不能再次加入
+        for &pid in running.get(&self.key.uid).into_iter().flatten() {
+            if !removed.contains(&pid) {
+                self.add_process(pid);
+            }
+        }
+    }
+
+    /// foreground 策略下任一进程位于前台时返回 true，其余策略返回 None
+    fn foreground(&self) -> Option<bool> {
+        (self.policy == SessionPolicy::Foreground)
+            .then(|| self.processes.keys().any(|&pid| is_process_in_top_app(pid)))
+    }
+
+    fn tracks_foreground(&self) -> bool {
+        self.policy == SessionPolicy::Foreground
+    }
+
+    fn pidfds(&self) -> impl Iterator<Item = RawFd> + '_ {
+        self.processes.values().flatten().map(AsRawFd::as_raw_fd)
+    }
+
+    fn needs_polling(&self) -> bool {
+        !self.unrestored.is_empty() || self.processes.values().any(Option::is_none)
+    }
+}
+
+/// 会话与属性仲裁表，二者总是一起修改
+#[derive(Default)]
+pub struct SessionTable {
+    sessions: Vec<Session>,
+    /// 已结束但还原失败的会话，保留日志直到重试成功
+    finishing: Vec<Session>,
+    owners: PropertyOwners,
+    next_id: SessionId,
+}
+
+impl SessionTable {
+    pub fn find(&mut self, key: &SessionKey) -> Option<&mut Session> {
+        self.sessions.iter_mut().find(|session| session.key == *key)
+    }
+
+    pub fn owners(&self) -> &PropertyOwners {
+        &self.owners
+    }
+
+    /// 应用伪装并登记会话；写入失败时撤销已写入的属性，撤销成功后删除日志，否则交给管理线程重试
+    pub fn start(
+        &mut self,
+        key: SessionKey,
+        pid: u32,
+        policy: SessionPolicy,
+        spoof: SpoofedProperties,
+        resetprop_path: String,
+        journal: Journal,
+    ) -> Result<(), (String, anyhow::Error)> {
+        self.next_id += 1;
+        let mut session = Session {
+            id: self.next_id,
+            key,
+            processes: HashMap::from([(pid, pidfd_open(pid))]),
+            policy,
+            spoof,
+            resetprop_path,
+            journal: Some(journal),
+            applied: false,
+            unrestored: Vec::new(),
+        };
+
+        if let Err(err) = session.activate(&mut self.owners, None) {
+            if session.deactivate(&mut self.owners) {
+                session.remove_journal();
+            } else {
+                session.processes.clear();
+                self.finishing.push(session);
+            }
+            return Err(err);
+        }
+        if policy == SessionPolicy::Reboot {
+            // 保留到重启的会话无需还原，也不应被回放
+            session.remove_journal();
+        }
+        self.sessions.push(session);
+        Ok(())
+    }
+}
+
+pub struct Supervisor {
+    table: Mutex<SessionTable>,
+    /// 新会话加入时唤醒管理线程
+    wake: OwnedFd,
+}
+
+impl Supervisor {
+    /// 获取会话管理线程，首次调用时启动
+    pub fn get() -> anyhow::Result<&'static Self> {
+        if let Some(supervisor) = SUPERVISOR.get() {
+            return Ok(supervisor);
+        }
+
+        let _guard = START_LOCK
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(supervisor) = SUPERVISOR.get() {
+            return Ok(supervisor);
+        }
+
+        let wake = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK) };
+        if wake < 0 {
+            anyhow::bail!("eventfd failed: {}", std::io::Error::last_os_error());
+        }
+        let wake = unsafe { OwnedFd::from_raw_fd(wake) };
+
+        // 线程启动成功后才保存实例，线程在实例保存前保持挂起
+        let handle = thread::Builder::new()
+            .name("df-supervisor".to_string())
+            .spawn(|| {
+                loop {
+                    if let Some(supervisor) = SUPERVISOR.get() {
+                        break supervisor.run();
+                    }
+                    thread::park();
+                }
+            })?;
+        let supervisor = SUPERVISOR.get_or_init(|| Self {
+            table: Mutex::new(SessionTable::default()),
+            wake,
+        });
+        handle.thread().unpark();
+        Ok(supervisor)
+    }
+
+    /// 持有会话表期间，管理线程不会还原或重新写入任何属性
+    pub fn lock(&self) -> std::sync::MutexGuard<'_, SessionTable> {
+        self.table
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// 会话表变化后唤醒管理线程
+    pub fn wake(&self) {
+        let value: u64 = 1;
+        unsafe {
+            libc::write(
+                self.wake.as_raw_fd(),
+                &value as *const u64 as *const libc::c_void,
+                std::mem::size_of::<u64>(),
+            )
+        };
+    }
+
+    fn run(&self) {
+        let mut events: Option<ForegroundEvents> = None;
+        // logcat 启动失败或退出后，等到下一个会话加入时再尝试
+        let mut spawn_events = true;
+        let mut recheck = false;
+
+        loop {
+            let (mut fds, needs_fallback) = {
+                let table = self.lock();
+                let sessions = &table.sessions;
+                let mut fds = vec![poll_fd(self.wake.as_raw_fd())];
+                fds.extend(sessions.iter().flat_map(Session::pidfds).map(poll_fd));
+
+                // 只在有会话需要感知前台变化时保持 logcat 运行
+                let tracks_foreground = sessions.iter().any(Session::tracks_foreground);
+                if !tracks_foreground {
+                    events = None;
+                } else if events.is_none() && spawn_events {
+                    events = ForegroundEvents::spawn();
+                    spawn_events = false;
+                }
+                let needs_fallback = (tracks_foreground && events.is_none())
+                    || !table.finishing.is_empty()
+                    || sessions.iter().any(Session::needs_polling);
+                (fds, needs_fallback)
+            };
+            if let Some(events) = &events {
+                fds.push(poll_fd(events.fd()));
+            }
+
+            let timeout = if recheck {
+                FOREGROUND_RECHECK_MS
+            } else if needs_fallback {
+                FALLBACK_POLL_MS
+            } else {
+                -1
+            };
+            let ready = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout) };
+            if ready < 0 {
+                let err = std::io::Error::last_os_error();
+                if err.kind() != std::io::ErrorKind::Interrupted {
+                    error!("Supervisor poll failed: {err}");
+                }
+                continue;
+            }
+
+            if fds[0].revents != 0 {
+                drain_eventfd(self.wake.as_raw_fd());
+                spawn_events = true;
+                recheck = true;
+            } else if ready == 0 {
+                recheck = false;
+            }
+            if let Some(current) = &mut events
+                && fds.last().is_some_and(|fd| fd.revents != 0)
+            {
+                if current.drain() {
+                    recheck = true;
+                } else {
+                    warn!("Foreground event source exited, falling back to polling");
+                    events = None;
+                }
+            }
+
+            // 已退出进程的 pidfd 会一直保持可读，必须在本轮结束对应会话
+            let exited: Vec<RawFd> = fds[1..]
+                .iter()
+                .filter(|fd| fd.revents != 0)
+                .map(|fd| fd.fd)
+                .collect();
+            self.update_sessions(&exited);
+        }
+    }
+
+    /// 重试还原失败的属性，刷新各会话的进程列表，结束没有存活进程的会话并处理前台变化
+    ///
+    /// 整个过程持有会话表，保证 companion 不会在还原途中为同一属性创建新的备份。
+    fn update_sessions(&self, exited: &[RawFd]) {
+        let mut table = self.lock();
+        table.finishing.retain_mut(|session| {
+            if !session.retry_restore() {
+                return true;
+            }
+            session.remove_journal();
+            false
+        });
+        if table.sessions.is_empty() {
+            return;
+        }
+
+        let running = running_processes_by_uid();
+        for session in table.sessions.iter_mut() {
+            session.refresh_processes(exited, &running);
+        }
+        let (ended, active): (Vec<Session>, Vec<Session>) = std::mem::take(&mut table.sessions)
+            .into_iter()
+            .partition(|session| session.processes.is_empty());
+        table.sessions = active;
+
+        let SessionTable {
+            sessions,
+            finishing,
+            owners,
+            ..
+        } = &mut *table;
+        finishing.extend(
+            ended
+                .into_iter()
+                .filter_map(|session| session.finish(owners)),
+        );
+        // 离开前台时还原失败的属性，回到前台前继续重试
+        for session in sessions.iter_mut().filter(|session| !session.applied) {
+            session.retry_restore();
+        }
+
+        // 先撤销离开前台的会话，再让进入前台的会话依次成为栈顶；
+        // 同一轮中无法区分进入前台的先后，按会话创建顺序生效，后创建的会话胜出
+        let foreground: Vec<Option<bool>> = sessions.iter().map(Session::foreground).collect();
+        for (session, foreground) in sessions.iter_mut().zip(&foreground) {
+            if session.applied && *foreground == Some(false) {
+                session.deactivate(owners);
+            }
+        }
+        let mut current = None;
+        for (session, foreground) in sessions.iter_mut().zip(&foreground) {
+            if session.applied || *foreground != Some(true) {
+                continue;
+            }
+            // 其他会话的撤销已经写完，读取一次即可供本轮所有会话使用
+            if current.is_none() {
+                match list_properties() {
+                    Ok(properties) => current = Some(properties),
+                    Err(e) => {
+                        error!("Failed to read properties before re-applying: {e:#}");
+                        return;
+                    }
+                }
+            }
+            if let Err((key, e)) = session.activate(owners, current.as_ref()) {
+                error!(
+                    "Failed to re-apply property {key} for {}: {e:#}",
+                    session.key.package
+                );
+            }
+        }
+    }
+}
+
+/// 跟随 logcat events 缓冲区中的前台切换事件，只用作检查时机，不解析内容
+struct ForegroundEvents {
+    child: Child,
+    stdout: ChildStdout,
+}
+
+impl ForegroundEvents {
+    fn spawn() -> Option<Self> {
+        let mut child = Command::new("logcat")
+            .args(["-b", "events", "-v", "raw", "-T", "1"])
+            .args(FOREGROUND_EVENT_TAGS)
+            .arg("*:S")
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .inspect_err(|e| warn!("Failed to start logcat for foreground events: {e}"))
+            .ok()?;
+        let stdout = child.stdout.take()?;
+
+        let fd = stdout.as_raw_fd();
+        unsafe {
+            let flags = libc::fcntl(fd, libc::F_GETFL);
+            libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK);
+        }
+        Some(Self { child, stdout })
+    }
+
+    fn fd(&self) -> RawFd {
+        self.stdout.as_raw_fd()
+    }
+
+    /// 读空管道，logcat 已退出时返回 false
+    fn drain(&mut self) -> bool {
+        let mut buffer = [0u8; 4096];
+        loop {
+            match self.stdout.read(&mut buffer) {
+                Ok(0) => return false,
+                Ok(_) => continue,
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => return true,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(_) => return false,
+            }
+        }
+    }
+}
+
+impl Drop for ForegroundEvents {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn poll_fd(fd: RawFd) -> libc::pollfd {
+    libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    }
+}
+
+fn drain_eventfd(fd: RawFd) {
+    let mut value: u64 = 0;
+    unsafe {
+        libc::read(
+            fd,
+            &mut value as *mut u64 as *mut libc::c_void,
+            std::mem::size_of::<u64>(),
+        )
+    };
+}
+
+/// Linux 5.3+ 提供 pidfd_open，进程退出时 pidfd 变为可读
+fn pidfd_open(pid: u32) -> Option<OwnedFd> {
+    let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid as libc::pid_t, 0) };
+    (fd >= 0).then(|| unsafe { OwnedFd::from_raw_fd(fd as RawFd) })
+}
+
+/// 扫描 /proc，按 uid 列出当前所有进程
+fn running_processes_by_uid() -> HashMap<u32, Vec<u32>> {
+    let mut processes: HashMap<u32, Vec<u32>> = HashMap::new();
+    let Ok(dir) = fs::read_dir("/proc") else {
+        return processes;
+    };
+    for entry in dir.flatten() {
+        let Some(pid) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        if let Ok(metadata) = entry.metadata() {
+            processes.entry(metadata.uid()).or_default().push(pid);
+        }
+    }
+    processes
+}
+
+fn is_process_alive(pid: u32) -> bool {
+    fs::metadata(format!("/proc/{pid}")).is_ok()
+}
+
+fn is_process_in_top_app(pid: u32) -> bool {
+    let cgroup_path = format!("/proc/{pid}/cgroup");
+    match fs::read_to_string(&cgroup_path) {
+        Ok(content) => content.lines().any(|line| line.contains("top-app")),
+        Err(_) => true,
+    }
+}
@@ -7,6 +7,11 @@ import { parse as parseToml, stringify as stringifyToml } from 'smol-toml'
 const CONFIG_PATH = '/data/adb/device_faker/config/config.toml'
 const MODULE_PROP_PATH = '/data/adb/modules/device_faker/module.prop'
 
+// 应用配置中的全部包名
+function appPackages(app: AppConfig): string[] {
+  return Array.isArray(app.package) ? app.package : [app.package]
+}
+
 export const useConfigStore = defineStore('config', () => {
   const config = ref<Config>({})
   const moduleVersion = ref('0.0.0')
@@ -86,7 +91,9 @@ export const useConfigStore = defineStore('config', () => {
     }
 
     // 统计直接配置的应用
-    count += apps.value.length
+    for (const app of apps.value) {
+      count += appPackages(app).length
+    }
 
     return count
   })
@@ -124,23 +131,40 @@ export const useConfigStore = defineStore('config', () => {
     }
   }
 
-  // 添加或更新应用配置
+  // 添加或更新应用配置；原配置的包名列表中还有其他包名时，只把这些包名移出列表
   function setApp(appConfig: AppConfig) {
     if (!config.value.apps) {
       config.value.apps = []
     }
-    const index = config.value.apps.findIndex((a: AppConfig) => a.package === appConfig.package)
-    if (index >= 0) {
-      config.value.apps[index] = appConfig
-    } else {
+    const packages = appPackages(appConfig)
+    const index = config.value.apps.findIndex((a: AppConfig) =>
+      appPackages(a).some((p) => packages.includes(p))
+    )
+    if (index < 0) {
+      config.value.apps.push(appConfig)
+      return
+    }
+
+    const existing = config.value.apps[index]
+    const remaining = appPackages(existing).filter((p) => !packages.includes(p))
+    if (remaining.length > 0) {
+      existing.package = remaining
       config.value.apps.push(appConfig)
+    } else {
+      config.value.apps[index] = appConfig
     }
   }
 
-  // 删除应用配置
+  // 删除应用配置；包名列表中还有其他包名时保留该配置
   function deleteApp(packageName: string) {
     if (config.value.apps) {
-      config.value.apps = config.value.apps.filter((a: AppConfig) => a.package !== packageName)
+      config.value.apps = config.value.apps.flatMap((a: AppConfig) => {
+        if (!appPackages(a).includes(packageName)) {
+          return [a]
+        }
+        const remaining = appPackages(a).filter((p) => p !== packageName)
+        return remaining.length > 0 ? [{ ...a, package: remaining }] : []
+      })
     }
   }
 
@@ -154,7 +178,7 @@ export const useConfigStore = defineStore('config', () => {
     }
 
     // 检查直接配置的应用
-    return apps.value.some((a) => a.package === packageName)
+    return apps.value.some((a) => appPackages(a).includes(packageName))
   }
 
   // 获取包名的配置
@@ -162,7 +186,7 @@ export const useConfigStore = defineStore('config', () => {
     packageName: string
   ): (Template & { source: string }) | AppConfig | null {
     // 先检查直接配置的应用（优先级更高）
-    const appConfig = apps.value.find((a) => a.package === packageName)
+    const appConfig = apps.value.find((a) => appPackages(a).includes(packageName))
     if (appConfig) {
       return appConfig
     }
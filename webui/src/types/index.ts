@@ -16,9 +16,9 @@ export interface Template extends DeviceInfo {
   mode?: 'lite' | 'full'
 }
 
-// 应用配置接口
+// 应用配置接口，package 可以是单个包名或包名列表
 export interface AppConfig extends DeviceInfo {
-  package: string
+  package: string | string[]
   mode?: 'lite' | 'full'
 }
 